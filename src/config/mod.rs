@@ -1,6 +1,14 @@
+use bevy::asset::io::file::FileAssetReader;
+use bevy::log;
+use bevy::prelude::Resource;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Duration;
+
 macro_rules! define_enum_and_to_string {
     ($enum_name:ident { $($variant_name:ident => $variant_str:expr),* $(,)? }) => {
-        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
         pub enum $enum_name {
             $($variant_name),*
         }
@@ -21,7 +29,372 @@ define_enum_and_to_string! {
         WhyHaRuHiKaGe => "ui/特效圖片/為什麼要演奏春日影！.png",
         MaZu => "ui/特效圖片/媽祖.png",
         Monkey => "ui/特效圖片/山道猴子.png",
-        NetArmy => "ui/特效圖片/為什麼要演奏春日影！.png",
+        NetArmy => "ui/特效圖片/網軍.png",
         GenShinStart => "genshin-start.png"
     }
 }
+
+define_enum_and_to_string! {
+    Difficulty {
+        Easy => "EASY",
+        Normal => "NORMAL",
+        Hard => "HARD",
+        Expert => "EXPERT",
+    }
+}
+
+define_enum_and_to_string! {
+    CombatAction {
+        Advance => "ADVANCE",
+        Attack => "ATTACK",
+        Defense => "DEFENSE",
+        Dodge => "DODGE",
+        Skill => "SKILL",
+    }
+}
+
+/// Largest distance from a note, in milliseconds, for each judgement.
+/// Anything further than `miss` is not judged at all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JudgementWindows {
+    pub perfect: u64,
+    pub good: u64,
+    pub ok: u64,
+    pub miss: u64,
+}
+
+impl JudgementWindows {
+    pub fn miss(&self) -> Duration {
+        Duration::from_millis(self.miss)
+    }
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 4] = [Self::Easy, Self::Normal, Self::Hard, Self::Expert];
+
+    pub fn windows(self) -> JudgementWindows {
+        let (perfect, good, ok, miss) = match self {
+            Self::Easy => (110, 170, 240, 300),
+            Self::Normal => (80, 125, 185, 250),
+            Self::Hard => (60, 95, 140, 200),
+            Self::Expert => (40, 70, 105, 150),
+        };
+        JudgementWindows {
+            perfect,
+            good,
+            ok,
+            miss,
+        }
+    }
+
+    /// The preset `step` places further along `ALL`, wrapping around.
+    pub fn cycle(self, step: isize) -> Self {
+        let len = Self::ALL.len() as isize;
+        let index = Self::ALL.iter().position(|d| *d == self).unwrap() as isize;
+        Self::ALL[(index + step).rem_euclid(len) as usize]
+    }
+}
+
+impl std::fmt::Display for JudgementWindows {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Perfect {}ms  Good {}ms\nOk {}ms  Miss {}ms",
+            self.perfect, self.good, self.ok, self.miss
+        )
+    }
+}
+
+/// Letter grade of a finished song, best first.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Rank {
+    SSS,
+    SS,
+    S,
+    A,
+    B,
+    C,
+    D,
+}
+
+impl std::fmt::Display for Rank {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+/// What a play needs to reach `rank`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RankThreshold {
+    pub rank: Rank,
+    /// Lowest accuracy, in percent.
+    pub accuracy: f32,
+    /// Most misses (wrong keys included) allowed, `None` for no limit.
+    #[serde(default)]
+    pub max_misses: Option<usize>,
+}
+
+/// Rank thresholds, read from `assets/config/ranks.ron`.
+#[derive(Debug, Clone, Resource, Serialize, Deserialize)]
+pub struct RankConfig {
+    /// Checked in order, the first one met gives the rank.
+    pub thresholds: Vec<RankThreshold>,
+}
+
+impl Default for RankConfig {
+    fn default() -> Self {
+        let threshold = |rank, accuracy, max_misses| RankThreshold {
+            rank,
+            accuracy,
+            max_misses,
+        };
+        Self {
+            thresholds: vec![
+                threshold(Rank::SSS, 100., Some(0)),
+                threshold(Rank::SS, 95., Some(0)),
+                threshold(Rank::S, 90., Some(5)),
+                threshold(Rank::A, 80., None),
+                threshold(Rank::B, 70., None),
+                threshold(Rank::C, 60., None),
+            ],
+        }
+    }
+}
+
+impl RankConfig {
+    /// Rank of a play, `Rank::D` when no threshold is met.
+    pub fn rank(&self, accuracy: f32, misses: usize) -> Rank {
+        self.thresholds
+            .iter()
+            .find(|t| accuracy >= t.accuracy && t.max_misses.is_none_or(|max| misses <= max))
+            .map_or(Rank::D, |t| t.rank)
+    }
+}
+
+/// What happens to a team whose life gauge runs out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FailBehaviour {
+    /// The match is over, and the team loses it.
+    EndMatch,
+    /// The team is marked as failed but plays on.
+    MarkFailed,
+}
+
+/// Life gauge tuning, read from `assets/config/life.ron`.
+#[derive(Debug, Clone, Resource, Serialize, Deserialize)]
+pub struct LifeConfig {
+    pub max: f32,
+    /// Life each team starts a match with.
+    pub start: f32,
+    pub miss_damage: f32,
+    pub wrong_key_damage: f32,
+    pub perfect_gain: f32,
+    pub good_gain: f32,
+    pub ok_gain: f32,
+    pub fail: FailBehaviour,
+}
+
+impl Default for LifeConfig {
+    fn default() -> Self {
+        Self {
+            max: 100.,
+            start: 100.,
+            miss_damage: 8.,
+            wrong_key_damage: 5.,
+            perfect_gain: 2.,
+            good_gain: 1.,
+            ok_gain: 0.,
+            fail: FailBehaviour::MarkFailed,
+        }
+    }
+}
+
+/// Base points of a successful hit, by judgement.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct JudgementScores {
+    pub perfect: usize,
+    pub good: usize,
+    pub ok: usize,
+}
+
+/// Formula a mode turns hits into points with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScoringKind {
+    /// The judgement's score plus the mode's `ComboCurve` bonus.
+    #[default]
+    ComboBonus,
+    /// The judgement's score alone; the combo only counts towards the tiers.
+    Flat,
+}
+
+/// How the combo adds to a hit's points.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ComboCurve {
+    /// Combo gained by each successful hit.
+    pub step: usize,
+    /// Points added for each combo already chained.
+    pub bonus: f32,
+    /// Combo past which the bonus stops growing, `None` for no limit.
+    #[serde(default)]
+    pub cap: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ComboTier {
+    /// Combo the tier starts at.
+    pub combo: usize,
+    /// Score multiplier for hits while in the tier.
+    pub multiplier: f32,
+}
+
+/// How many points bring out the crowd.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct CrowdThresholds {
+    /// One crowd member for every this many points.
+    pub people_every: usize,
+    /// A hit crossing this many points at least `cats_min` times also brings
+    /// out a cat per crowd member.
+    pub cats_every: usize,
+    pub cats_min: usize,
+}
+
+/// One game mode's scoring and match rules.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameMode {
+    pub name: String,
+    #[serde(default)]
+    pub scoring: ScoringKind,
+    pub scores: JudgementScores,
+    /// Only used by `ScoringKind::ComboBonus`.
+    #[serde(default)]
+    pub combo: ComboCurve,
+    /// Combo tiers, lowest first.
+    pub combo_tiers: Vec<ComboTier>,
+    pub crowd: CrowdThresholds,
+    /// Longest a match can last, in seconds, however long the chart is.
+    pub match_length: u64,
+}
+
+impl GameMode {
+    pub fn match_length(&self) -> Duration {
+        Duration::from_secs(self.match_length)
+    }
+}
+
+impl Default for ComboCurve {
+    fn default() -> Self {
+        Self {
+            step: 1,
+            bonus: 0.,
+            cap: None,
+        }
+    }
+}
+
+impl Default for GameMode {
+    fn default() -> Self {
+        let tier = |combo, multiplier| ComboTier { combo, multiplier };
+        Self {
+            name: "Classic".to_string(),
+            scoring: ScoringKind::ComboBonus,
+            scores: JudgementScores {
+                perfect: 3,
+                good: 3,
+                ok: 3,
+            },
+            combo: ComboCurve {
+                step: 1,
+                bonus: 1.,
+                cap: None,
+            },
+            combo_tiers: vec![tier(10, 1.2), tier(25, 1.5), tier(50, 2.), tier(100, 3.)],
+            crowd: CrowdThresholds {
+                people_every: 5,
+                cats_every: 10,
+                cats_min: 2,
+            },
+            match_length: 180,
+        }
+    }
+}
+
+/// Game modes to pick from, read from `assets/config/modes.ron`.
+#[derive(Debug, Clone, Resource, Serialize, Deserialize)]
+pub struct GameModes {
+    pub modes: Vec<GameMode>,
+    /// Index of the mode the next match is played in.
+    #[serde(skip)]
+    pub selected: usize,
+}
+
+impl Default for GameModes {
+    fn default() -> Self {
+        Self {
+            modes: vec![GameMode::default()],
+            selected: 0,
+        }
+    }
+}
+
+impl GameModes {
+    /// The selected mode, the built-in one if the file has none.
+    pub fn current(&self) -> GameMode {
+        self.modes.get(self.selected).cloned().unwrap_or_default()
+    }
+
+    /// Select the mode `step` places further along, wrapping around.
+    pub fn cycle(&mut self, step: isize) {
+        let len = self.modes.len().max(1) as isize;
+        self.selected = (self.selected as isize + step).rem_euclid(len) as usize;
+    }
+}
+
+/// Read the game config file `name` from `assets/config`, falling back to the
+/// built-in defaults if it is missing or unreadable.
+pub fn load_config<T: DeserializeOwned + Default>(name: &str) -> T {
+    let path = FileAssetReader::get_base_path()
+        .join("assets")
+        .join("config")
+        .join(name);
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) => {
+            log::warn!(path = ?path, "using default config: {e}");
+            return T::default();
+        }
+    };
+    ron::from_str(&text).unwrap_or_else(|e| {
+        log::error!(path = ?path, "using default config: {e}");
+        T::default()
+    })
+}
+
+/// Machine-local settings file `name`, kept next to the `assets` directory.
+pub fn settings_path(name: &str) -> PathBuf {
+    FileAssetReader::get_base_path().join(name)
+}
+
+/// Read the settings file `name`, falling back to the defaults if it is missing
+/// or unreadable.
+pub fn load_settings<T: DeserializeOwned + Default>(name: &str) -> T {
+    let path = settings_path(name);
+    let Ok(text) = std::fs::read_to_string(&path) else {
+        return T::default();
+    };
+    ron::from_str(&text).unwrap_or_else(|e| {
+        log::warn!(path = ?path, "ignoring settings file: {e}");
+        T::default()
+    })
+}
+
+pub fn save_settings<T: Serialize>(name: &str, settings: &T) {
+    let path = settings_path(name);
+    let result = ron::ser::to_string_pretty(settings, Default::default())
+        .map_err(|e| e.to_string())
+        .and_then(|text| std::fs::write(&path, text).map_err(|e| e.to_string()));
+    match result {
+        Ok(()) => log::info!(path = ?path, "settings saved"),
+        Err(e) => log::error!(path = ?path, "could not save settings: {e}"),
+    }
+}
@@ -40,4 +40,11 @@ enum AppState {
     Menu,
     CharacterSelection,
     InGame,
+    Results,
+    Editor,
+    Calibration,
+    /// Watching another machine's matches, waiting for the next one.
+    SpectatorLobby,
+    /// Showing another machine's match as it is played.
+    Spectating,
 }
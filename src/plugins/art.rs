@@ -6,6 +6,8 @@ use rand::prelude::*;
 use std::time::Duration;
 
 use crate::plugins::score::GenEvent;
+use crate::plugins::{team_row, team_side};
+use crate::AppState;
 
 #[derive(Debug, Component)]
 pub struct JumpPeopleImage {
@@ -14,13 +16,22 @@ pub struct JumpPeopleImage {
     pub(crate) to: Vec2,
 }
 
-/// play one-shot SFV
-// pub struct PlaySfxEvent;
+/// How much closer to the middle each further pair of teams has its chair.
+const CHAIR_ROW_GAP: f32 = 250.;
 
-pub struct ArtPlugin;
+/// A jumping crowd sprite, cleared when the match ends.
+#[derive(Debug, Component)]
+struct Crowd;
+
+/// A team's sedan chair; the jumping sprite is its child, so the chair itself
+/// can be moved across the field.
+#[derive(Debug, Component)]
+pub struct SedanChair {
+    /// Where the chair stands when neither team is ahead.
+    pub home_x: f32,
+}
 
-#[derive(Debug, Event)]
-pub struct PeopleEntity(Entity);
+pub struct ArtPlugin;
 
 pub fn create_people_system(
     mut commands: Commands,
@@ -45,7 +56,7 @@ pub fn create_people_system(
         .with_repeat_strategy(RepeatStrategy::MirroredRepeat)
         .with_repeat_count(RepeatCount::Infinite);
 
-        commands.spawn((
+        let sprite = (
             // Spawn a Sprite entity to animate the position of.
             SpriteBundle {
                 sprite: Sprite {
@@ -61,8 +72,14 @@ pub fn create_people_system(
             },
             // Add an Animator component to control and execute the animation.
             Animator::new(pos_tween),
-        ));
-        commands.entity(ent).remove::<JumpPeopleImage>();
+        );
+        commands
+            .entity(ent)
+            .remove::<JumpPeopleImage>()
+            .insert(Crowd)
+            .with_children(|parent| {
+                parent.spawn(sprite);
+            });
     }
 }
 
@@ -75,48 +92,51 @@ pub fn gen_people(mut commands: Commands, mut evt_r: EventReader<GenEvent>) {
         let hi = 800.;
         let mut rng = thread_rng();
         let r = rng.gen_range(lo..hi);
-        if e.0 == 1 {
-            //player 1
-            if e.1 == 1 {
-                commands.spawn(JumpPeopleImage {
-                    img_name: "images/people_1.png".to_string(),
-                    from: Vec2 { x: -r, y: floor },
-                    to: Vec2 { x: -r, y: roof },
-                });
-            } else if e.1 == 2 {
-                commands.spawn(JumpPeopleImage {
-                    img_name: "images/cat_1.png".to_string(),
-                    from: Vec2 { x: -r, y: floor },
-                    to: Vec2 { x: -r, y: roof },
-                });
-            } else {
-                commands.spawn(JumpPeopleImage {
-                    img_name: "images/sedan_chair_1.png".to_string(),
-                    from: Vec2 { x: -che, y: floor },
-                    to: Vec2 { x: -che, y: roof },
-                });
-            }
+        // each half of the field has its own art, shared by the teams on it
+        let side = team_side(e.0);
+        let art = if side < 0. { 1 } else { 2 };
+        if e.1 == 1 {
+            commands.spawn((
+                JumpPeopleImage {
+                    img_name: format!("images/people_{art}.png"),
+                    from: Vec2 {
+                        x: side * r,
+                        y: floor,
+                    },
+                    to: Vec2 {
+                        x: side * r,
+                        y: roof,
+                    },
+                },
+                SpatialBundle::default(),
+            ));
+        } else if e.1 == 2 {
+            commands.spawn((
+                JumpPeopleImage {
+                    img_name: format!("images/cat_{art}.png"),
+                    from: Vec2 {
+                        x: side * r,
+                        y: floor,
+                    },
+                    to: Vec2 {
+                        x: side * r,
+                        y: roof,
+                    },
+                },
+                SpatialBundle::default(),
+            ));
         } else {
-            //player2
-            if e.1 == 1 {
-                commands.spawn(JumpPeopleImage {
-                    img_name: "images/people_2.png".to_string(),
-                    from: Vec2 { x: r, y: floor },
-                    to: Vec2 { x: r, y: roof },
-                });
-            } else if e.1 == 2 {
-                commands.spawn(JumpPeopleImage {
-                    img_name: "images/cat_2.png".to_string(),
-                    from: Vec2 { x: r, y: floor },
-                    to: Vec2 { x: r, y: roof },
-                });
-            } else {
-                commands.spawn(JumpPeopleImage {
-                    img_name: "images/sedan_chair_2.png".to_string(),
-                    from: Vec2 { x: che, y: floor },
-                    to: Vec2 { x: che, y: roof },
-                });
-            }
+            // later teams' chairs line up in front of the first pair's
+            let home_x = side * (che - team_row(e.0) as f32 * CHAIR_ROW_GAP);
+            commands.spawn((
+                JumpPeopleImage {
+                    img_name: format!("images/sedan_chair_{art}.png"),
+                    from: Vec2 { x: 0., y: floor },
+                    to: Vec2 { x: 0., y: roof },
+                },
+                SedanChair { home_x },
+                SpatialBundle::from_transform(Transform::from_xyz(home_x, 0., 0.)),
+            ));
         }
     }
 }
@@ -135,7 +155,14 @@ pub fn gen_people(mut commands: Commands, mut evt_r: EventReader<GenEvent>) {
 
 impl Plugin for ArtPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, (create_people_system, gen_people));
+        app.add_systems(Update, (create_people_system, gen_people))
+            .add_systems(OnExit(AppState::InGame), clear_crowd);
+    }
+}
+
+fn clear_crowd(mut commands: Commands, query: Query<Entity, With<Crowd>>) {
+    for ent in &query {
+        commands.entity(ent).despawn_recursive();
     }
 }
 
@@ -0,0 +1,325 @@
+use crate::config::{load_settings, save_settings};
+use crate::plugins::clock::SongClock;
+use crate::plugins::input::{PlayerCommand, PlayerCommandEvent};
+use crate::AppState;
+use bevy::{log, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+const LATENCY_FILE: &str = "latency.ron";
+const CLICK_INTERVAL: Duration = Duration::from_millis(600);
+/// Clicks per phase; the first `LEAD_IN` only set the pace and are not measured.
+const CLICKS: u32 = 20;
+const LEAD_IN: u32 = 4;
+/// Fewer taps than this and the phase keeps the previous offset.
+const MIN_TAPS: usize = 8;
+const FLASH_DURATION: Duration = Duration::from_millis(100);
+const TEXT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+
+/// Measure how late players hear and see the game, see `Latency`.
+///
+/// A click is played, then a flash is shown without sound, at a steady pace;
+/// every tap is compared to the nearest one.
+#[derive(Debug)]
+pub struct CalibrationPlugin;
+
+impl Plugin for CalibrationPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(load_settings::<Latency>(LATENCY_FILE))
+            .add_systems(OnEnter(AppState::Calibration), setup_calibration)
+            .add_systems(OnExit(AppState::Calibration), cleanup_calibration)
+            .add_systems(
+                Update,
+                (
+                    calibration_controls,
+                    click_system,
+                    record_taps,
+                    flash_system,
+                    status_text_update_system,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Calibration)),
+            );
+    }
+}
+
+/// Per-machine offsets, in milliseconds, measured by the calibration screen.
+#[derive(Debug, Clone, Copy, Default, Resource, Serialize, Deserialize)]
+pub struct Latency {
+    /// How long after a sound players hit to it; taken off input times before judging.
+    pub audio: i32,
+    /// How long after a flash players hit to it.
+    pub visual: i32,
+}
+
+impl Latency {
+    /// Song position an input read at `time` is judged at.
+    pub fn input_time(&self, time: Duration) -> Duration {
+        shift(time, -self.audio)
+    }
+
+    /// When an input has to be read to be judged at `time`, the other way
+    /// round from `input_time`.
+    pub fn read_time(&self, time: Duration) -> Duration {
+        shift(time, self.audio)
+    }
+
+    /// When a note due at `hit_point` should be drawn on its ring, so that
+    /// hitting it by eye lands as late as hitting it by ear does.
+    pub fn display_time(&self, hit_point: Duration) -> Duration {
+        shift(hit_point, self.audio - self.visual)
+    }
+}
+
+fn shift(time: Duration, ms: i32) -> Duration {
+    let offset = Duration::from_millis(ms.unsigned_abs() as u64);
+    if ms < 0 {
+        time.saturating_sub(offset)
+    } else {
+        time + offset
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    Audio,
+    Visual,
+    Done,
+}
+
+#[derive(Debug, Resource)]
+struct Calibration {
+    phase: Phase,
+    /// Song position of the phase's first click.
+    start: Duration,
+    /// Index of the next click to play or flash.
+    next_click: u32,
+    /// Distance of each measured tap from its click, in milliseconds.
+    taps: Vec<f32>,
+    /// Offsets measured so far, `None` for a phase without enough taps.
+    audio: Option<i32>,
+    visual: Option<i32>,
+}
+
+impl Calibration {
+    fn new(start: Duration) -> Self {
+        Self {
+            phase: Phase::Audio,
+            start,
+            next_click: 0,
+            taps: Vec::new(),
+            audio: None,
+            visual: None,
+        }
+    }
+
+    fn click_time(&self, click: u32) -> Duration {
+        self.start + CLICK_INTERVAL * click
+    }
+
+    fn mean_offset(&self) -> Option<i32> {
+        if self.taps.len() < MIN_TAPS {
+            return None;
+        }
+        let mean = self.taps.iter().sum::<f32>() / self.taps.len() as f32;
+        Some(mean.round() as i32)
+    }
+}
+
+#[derive(Debug, Component)]
+struct CalibrationTag;
+
+#[derive(Debug, Component)]
+struct Flash;
+
+#[derive(Debug, Component)]
+struct StatusText;
+
+#[derive(Resource)]
+struct ClickSound(Handle<AudioSource>);
+
+fn setup_calibration(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut clock: ResMut<SongClock>,
+) {
+    clock.reset();
+    clock.start(Instant::now());
+    // leave a moment before the first click
+    commands.insert_resource(Calibration::new(clock.now() + CLICK_INTERVAL * 2));
+    commands.insert_resource(ClickSound(asset_server.load("sounds/gong.ogg")));
+
+    commands.spawn((
+        SpriteBundle {
+            texture: asset_server.load("images/ui/game/white.png"),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        Flash,
+        CalibrationTag,
+    ));
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 30.,
+                color: TEXT_COLOR,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(5.),
+            left: Val::Px(5.),
+            ..default()
+        }),
+        StatusText,
+        CalibrationTag,
+    ));
+}
+
+fn cleanup_calibration(
+    mut commands: Commands,
+    query: Query<Entity, With<CalibrationTag>>,
+    mut clock: ResMut<SongClock>,
+) {
+    for ent in &query {
+        commands.entity(ent).despawn_recursive();
+    }
+    commands.remove_resource::<Calibration>();
+    commands.remove_resource::<ClickSound>();
+    clock.reset();
+}
+
+fn calibration_controls(
+    keys: Res<Input<KeyCode>>,
+    mut calibration: ResMut<Calibration>,
+    mut latency: ResMut<Latency>,
+    clock: Res<SongClock>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if keys.just_pressed(KeyCode::R) {
+        *calibration = Calibration::new(clock.now() + CLICK_INTERVAL * 2);
+    }
+    if keys.just_pressed(KeyCode::M) {
+        next_state.set(AppState::Menu);
+    }
+    if keys.just_pressed(KeyCode::Return) && calibration.phase == Phase::Done {
+        latency.audio = calibration.audio.unwrap_or(latency.audio);
+        latency.visual = calibration.visual.unwrap_or(latency.visual);
+        save_settings(LATENCY_FILE, &*latency);
+        next_state.set(AppState::Menu);
+    }
+}
+
+/// Play (or flash) the clicks on time and move on to the next phase once a
+/// phase's clicks are over.
+fn click_system(
+    mut commands: Commands,
+    mut calibration: ResMut<Calibration>,
+    click_sound: Res<ClickSound>,
+    clock: Res<SongClock>,
+) {
+    let elapsed = clock.now();
+    while calibration.phase != Phase::Done
+        && calibration.click_time(calibration.next_click) <= elapsed
+    {
+        if calibration.next_click == CLICKS {
+            let offset = calibration.mean_offset();
+            log::info!(phase = ?calibration.phase, offset, "calibration phase done");
+            calibration.phase = match calibration.phase {
+                Phase::Audio => {
+                    calibration.audio = offset;
+                    Phase::Visual
+                }
+                _ => {
+                    calibration.visual = offset;
+                    Phase::Done
+                }
+            };
+            calibration.start = elapsed + CLICK_INTERVAL * 2;
+            calibration.next_click = 0;
+            calibration.taps.clear();
+            break;
+        }
+
+        if calibration.phase == Phase::Audio {
+            commands.spawn(AudioBundle {
+                source: click_sound.0.clone(),
+                settings: PlaybackSettings::DESPAWN,
+            });
+        }
+        calibration.next_click += 1;
+    }
+}
+
+fn record_taps(
+    mut player_command_evt: EventReader<PlayerCommandEvent>,
+    mut calibration: ResMut<Calibration>,
+) {
+    for e in player_command_evt.read() {
+        if !e.pressed
+            || matches!(e.cmd, PlayerCommand::Ex | PlayerCommand::Exit)
+            || calibration.phase == Phase::Done
+        {
+            continue;
+        }
+
+        // the nearest click, which may not have been played yet
+        let since_start = e.time.as_secs_f32() - calibration.start.as_secs_f32();
+        let click = (since_start / CLICK_INTERVAL.as_secs_f32()).round();
+        if click < LEAD_IN as f32 || click >= CLICKS as f32 {
+            continue;
+        }
+        let offset = since_start - click * CLICK_INTERVAL.as_secs_f32();
+        calibration.taps.push(offset * 1000.);
+    }
+}
+
+fn flash_system(
+    calibration: Res<Calibration>,
+    clock: Res<SongClock>,
+    mut query: Query<&mut Visibility, With<Flash>>,
+) {
+    let elapsed = clock.now();
+    let lit = calibration.phase == Phase::Visual
+        && calibration.next_click > 0
+        && elapsed < calibration.click_time(calibration.next_click - 1) + FLASH_DURATION;
+    for mut visibility in &mut query {
+        *visibility = if lit {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
+
+fn status_text_update_system(
+    calibration: Res<Calibration>,
+    latency: Res<Latency>,
+    mut query: Query<&mut Text, With<StatusText>>,
+) {
+    let measured = |offset: Option<i32>| match offset {
+        Some(offset) => format!("{offset}ms"),
+        None => "-".to_string(),
+    };
+    let instructions = match calibration.phase {
+        Phase::Audio => "Tap any hit key along with the click",
+        Phase::Visual => "Tap any hit key along with the flash",
+        Phase::Done => "[Enter] save  [R] again",
+    };
+
+    for mut text in &mut query {
+        text.sections[0].value = format!(
+            "CALIBRATION\n{instructions}  [M] menu\n\
+            taps {}\n\
+            audio {} (was {}ms)\n\
+            visual {} (was {}ms)",
+            calibration.taps.len(),
+            measured(calibration.audio),
+            latency.audio,
+            measured(calibration.visual),
+            latency.visual,
+        );
+    }
+}
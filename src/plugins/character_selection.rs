@@ -1,4 +1,9 @@
-use crate::AppState::CharacterSelection;
+use crate::config::{Difficulty, GameModes};
+use crate::plugins::chart::{Chart, SelectedChart};
+use crate::plugins::effects::MatchSeed;
+use crate::plugins::input::{key_label, PlayerCommand, PlayerCommandEvent, MAX_TEAMS};
+use crate::plugins::net::NetSession;
+use crate::AppState::{self, CharacterSelection};
 use bevy::prelude::*;
 
 #[derive(Debug)]
@@ -9,18 +14,132 @@ struct CharacterSelectionMenuTag;
 
 impl Plugin for CharacterSelectionPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            OnEnter(CharacterSelection),
-            (setup_character_menu, hover_feedback_system),
-        )
-        .add_systems(OnExit(CharacterSelection), cleanup_menu);
+        app.init_resource::<PlayerSettings>()
+            .add_systems(
+                OnEnter(CharacterSelection),
+                (setup_character_menu, hover_feedback_system),
+            )
+            .add_systems(
+                Update,
+                (
+                    player_count_system,
+                    game_mode_system,
+                    player_setup_system,
+                    player_panel_update_system,
+                )
+                    .chain()
+                    .run_if(in_state(CharacterSelection)),
+            )
+            .add_systems(OnExit(CharacterSelection), cleanup_menu);
     }
 }
 
+/// What each player picked for the next match.
+#[derive(Debug, Resource)]
+pub struct PlayerSettings(Vec<PlayerSetting>);
+
+#[derive(Debug, Clone)]
+pub struct PlayerSetting {
+    pub difficulty: Difficulty,
+    /// Index of the chart track this player plays.
+    pub track: usize,
+    /// How fast notes travel to the ring, relative to the default approach time.
+    pub scroll_speed: f32,
+    /// Show the hit-error meter under the ring.
+    pub hit_error_meter: bool,
+}
+
+/// Scroll speeds players can pick from.
+const SCROLL_SPEEDS: [f32; 8] = [0.5, 0.75, 1., 1.25, 1.5, 2., 2.5, 3.];
+
+fn cycle_scroll_speed(speed: f32, step: isize) -> f32 {
+    let len = SCROLL_SPEEDS.len() as isize;
+    let index = SCROLL_SPEEDS.iter().position(|s| *s == speed).unwrap_or(2) as isize;
+    SCROLL_SPEEDS[(index + step).rem_euclid(len) as usize]
+}
+
+impl Default for PlayerSetting {
+    fn default() -> Self {
+        Self {
+            difficulty: Difficulty::Normal,
+            track: 0,
+            scroll_speed: 1.,
+            hit_error_meter: true,
+        }
+    }
+}
+
+impl Default for PlayerSettings {
+    fn default() -> Self {
+        Self(vec![PlayerSetting::default(); MIN_TEAMS])
+    }
+}
+
+impl PlayerSettings {
+    pub fn teams(&self) -> impl Iterator<Item = i32> {
+        1..=self.0.len() as i32
+    }
+
+    pub fn get(&self, team: i32) -> &PlayerSetting {
+        &self.0[(team - 1) as usize]
+    }
+
+    pub(crate) fn get_mut(&mut self, team: i32) -> &mut PlayerSetting {
+        &mut self.0[(team - 1) as usize]
+    }
+
+    /// Play with `count` teams; teams that stay keep their settings.
+    pub fn set_count(&mut self, count: usize) {
+        self.0.resize(count, PlayerSetting::default());
+    }
+}
+
+/// Fewest teams a match can have.
+const MIN_TEAMS: usize = 2;
+
+/// The number of teams, changed with Tab.
+#[derive(Debug, Component)]
+struct PlayerCountText;
+
+/// The game mode of the match, changed with F.
+#[derive(Debug, Component)]
+struct GameModeText;
+
+/// One player's setup text under their token.
+#[derive(Debug, Component)]
+struct PlayerPanel {
+    team: i32,
+    /// Index into `PANEL_ROWS` of the setting being changed.
+    row: usize,
+    ready: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PanelRow {
+    Difficulty,
+    Track,
+    ScrollSpeed,
+    HitErrorMeter,
+}
+
+const PANEL_ROWS: [PanelRow; 4] = [
+    PanelRow::Difficulty,
+    PanelRow::Track,
+    PanelRow::ScrollSpeed,
+    PanelRow::HitErrorMeter,
+];
+
 const NORMAL_COLOR: Color = Color::rgb(0.7, 0.7, 0.7);
 const HOVERED_COLOR: Color = Color::rgb(1., 1., 1.);
+const PANEL_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+const READY_COLOR: Color = Color::rgb(1.0, 0.8, 0.2);
+const PANEL_FONT_SIZE: f32 = 30.;
 
-fn setup_character_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
+fn setup_character_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    settings: Res<PlayerSettings>,
+) {
     let img_path = "images/ui/scenes/選角畫面.png";
     let bg_img = asset_server.load(img_path);
 
@@ -66,6 +185,262 @@ fn setup_character_menu(mut commands: Commands, asset_server: Res<AssetServer>)
     let right_pos = Vec3::new(500., -75., 0.);
 
     spawn_generator(right_pos, right_party_img);
+
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: PANEL_FONT_SIZE,
+                color: PANEL_COLOR,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.),
+            left: Val::Px(10.),
+            ..default()
+        }),
+        PlayerCountText,
+        CharacterSelectionMenuTag,
+    ));
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: PANEL_FONT_SIZE,
+                color: PANEL_COLOR,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10. + PANEL_FONT_SIZE * 1.5),
+            left: Val::Px(10.),
+            ..default()
+        }),
+        GameModeText,
+        CharacterSelectionMenuTag,
+    ));
+    spawn_player_panels(&mut commands, settings.teams().count());
+}
+
+/// One panel per team, spread evenly across the bottom of the screen.
+fn spawn_player_panels(commands: &mut Commands, count: usize) {
+    for team in 1..=count as i32 {
+        let style = TextStyle {
+            font_size: PANEL_FONT_SIZE,
+            color: PANEL_COLOR,
+            ..default()
+        };
+        let hint = format!(
+            "[{}/{}] change  [{}] next",
+            key_label(team, 1),
+            key_label(team, 3),
+            key_label(team, 2)
+        );
+        let position = Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(40.),
+            left: Val::Percent((team - 1) as f32 * 100. / count as f32 + 6.),
+            ..default()
+        };
+
+        commands.spawn((
+            TextBundle::from_sections([
+                TextSection::from_style(style.clone()),
+                TextSection::new(format!("\n{hint}"), style),
+            ])
+            .with_style(position),
+            PlayerPanel {
+                team,
+                row: 0,
+                ready: false,
+            },
+            CharacterSelectionMenuTag,
+        ));
+    }
+}
+
+/// Tab changes how many teams play, which starts every panel over. A network
+/// match is always two players, one on each machine.
+fn player_count_system(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    mut settings: ResMut<PlayerSettings>,
+    net: Option<Res<NetSession>>,
+    panel_query: Query<Entity, With<PlayerPanel>>,
+    mut text_query: Query<&mut Text, With<PlayerCountText>>,
+) {
+    if keys.just_pressed(KeyCode::Tab) && net.is_none() {
+        let count = settings.teams().count() % MAX_TEAMS + 1;
+        settings.set_count(count.max(MIN_TEAMS));
+        for ent in &panel_query {
+            commands.entity(ent).despawn();
+        }
+        spawn_player_panels(&mut commands, settings.teams().count());
+    }
+
+    for mut text in &mut text_query {
+        text.sections[0].value = format!("[Tab] {} players", settings.teams().count());
+    }
+}
+
+/// F cycles through the game modes of `assets/config/modes.ron`; in a network
+/// match the host picks.
+fn game_mode_system(
+    keys: Res<Input<KeyCode>>,
+    mut modes: ResMut<GameModes>,
+    net: Option<Res<NetSession>>,
+    mut text_query: Query<&mut Text, With<GameModeText>>,
+) {
+    if keys.just_pressed(KeyCode::F) && net.is_none_or(|net| net.is_host()) {
+        modes.cycle(1);
+    }
+
+    let mode = modes.current();
+    for mut text in &mut text_query {
+        text.sections[0].value = format!(
+            "[F] {} mode  ({}:{:02} max)",
+            mode.name,
+            mode.match_length / 60,
+            mode.match_length % 60
+        );
+    }
+}
+
+/// Each player sets up their panel and readies up; the match starts once all
+/// are ready. Over the network the peer's panel shows what it sent, and the
+/// host starts the match on both machines.
+#[allow(clippy::too_many_arguments)]
+fn player_setup_system(
+    mut player_command_evt: EventReader<PlayerCommandEvent>,
+    mut settings: ResMut<PlayerSettings>,
+    mut panel_query: Query<&mut PlayerPanel>,
+    selected_chart: Res<SelectedChart>,
+    charts: Res<Assets<Chart>>,
+    modes: Res<GameModes>,
+    seed: Res<MatchSeed>,
+    mut net: Option<ResMut<NetSession>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let track_count = charts
+        .get(&selected_chart.0)
+        .map_or(1, |chart| chart.tracks.len());
+
+    for e in player_command_evt.read() {
+        if !e.pressed {
+            continue;
+        }
+        let Some(mut panel) = panel_query.iter_mut().find(|p| p.team == e.team) else {
+            continue;
+        };
+
+        let step = match e.cmd {
+            PlayerCommand::Hit1 => -1,
+            PlayerCommand::Hit3 => 1,
+            PlayerCommand::Hit2 => {
+                if panel.ready {
+                    panel.ready = false;
+                    panel.row = 0;
+                } else if panel.row + 1 < PANEL_ROWS.len() {
+                    panel.row += 1;
+                } else {
+                    panel.ready = true;
+                }
+                continue;
+            }
+            PlayerCommand::Ex | PlayerCommand::Exit => continue,
+        };
+        if panel.ready {
+            continue;
+        }
+
+        let setting = settings.get_mut(e.team);
+        match PANEL_ROWS[panel.row] {
+            PanelRow::Difficulty => {
+                setting.difficulty = setting.difficulty.cycle(step);
+            }
+            PanelRow::Track => {
+                let len = track_count as isize;
+                setting.track = (setting.track as isize + step).rem_euclid(len) as usize;
+            }
+            PanelRow::ScrollSpeed => {
+                setting.scroll_speed = cycle_scroll_speed(setting.scroll_speed, step);
+            }
+            PanelRow::HitErrorMeter => {
+                setting.hit_error_meter = !setting.hit_error_meter;
+            }
+        }
+    }
+
+    if let Some(net) = &mut net {
+        for mut panel in &mut panel_query {
+            if panel.team == net.remote_team() {
+                panel.ready = net.peer_ready();
+            } else {
+                net.share_player(settings.get(panel.team), panel.ready);
+            }
+        }
+    }
+
+    if panel_query.iter().all(|p| p.ready) {
+        match &mut net {
+            None => next_state.set(AppState::InGame),
+            Some(net) if net.is_host() => {
+                net.begin(seed.0, modes.selected, &settings);
+                next_state.set(AppState::InGame);
+            }
+            // waiting for the host's `Begin`
+            Some(_) => {}
+        }
+    }
+}
+
+fn player_panel_update_system(
+    settings: Res<PlayerSettings>,
+    selected_chart: Res<SelectedChart>,
+    charts: Res<Assets<Chart>>,
+    mut query: Query<(&PlayerPanel, &mut Text)>,
+) {
+    let chart = charts.get(&selected_chart.0);
+    for (panel, mut text) in &mut query {
+        let setting = settings.get(panel.team);
+        let marker = |row: PanelRow| {
+            if !panel.ready && PANEL_ROWS[panel.row] == row {
+                "> "
+            } else {
+                "  "
+            }
+        };
+        let track = chart.map_or_else(
+            || "...".to_string(),
+            |chart| {
+                let track = chart.track(setting.track);
+                format!("{} ({} lanes)", track.name, track.lanes.len())
+            },
+        );
+
+        text.sections[0].value = format!(
+            "P{}{}\n{}{}\n{}\n{}Chart: {}\n{}Speed: {:.2}x\n{}Meter: {}",
+            panel.team,
+            if panel.ready { "  READY" } else { "" },
+            marker(PanelRow::Difficulty),
+            setting.difficulty,
+            setting.difficulty.windows(),
+            marker(PanelRow::Track),
+            track,
+            marker(PanelRow::ScrollSpeed),
+            setting.scroll_speed,
+            marker(PanelRow::HitErrorMeter),
+            if setting.hit_error_meter { "ON" } else { "OFF" },
+        );
+        text.sections[0].style.color = if panel.ready {
+            READY_COLOR
+        } else {
+            PANEL_COLOR
+        };
+    }
 }
 
 fn hover_feedback_system(mut query: Query<(&Interaction, &mut Sprite), Changed<Interaction>>) {
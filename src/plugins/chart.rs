@@ -0,0 +1,274 @@
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, AsyncReadExt, LoadContext};
+use bevy::prelude::*;
+use bevy::reflect::TypePath;
+use bevy::utils::BoxedFuture;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+use std::time::Duration;
+use thiserror::Error;
+
+/// The chart played when a match starts.
+pub const DEFAULT_CHART: &str = "charts/default.chart.ron";
+/// Lanes a track can use, same numbering as `PlayerCommand::Hit1..=Hit3`.
+pub const LANES: RangeInclusive<i32> = 1..=3;
+/// Seconds into the track the last note of a chart has to end by.
+const MAX_SONG_LENGTH: f32 = 3600.;
+
+#[derive(Debug)]
+pub struct ChartPlugin;
+
+impl Plugin for ChartPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<Chart>()
+            .init_asset_loader::<ChartLoader>()
+            .add_systems(Startup, load_selected_chart);
+    }
+}
+
+/// The chart matches are played with.
+#[derive(Debug, Resource)]
+pub struct SelectedChart(pub Handle<Chart>);
+
+fn load_selected_chart(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(SelectedChart(asset_server.load(DEFAULT_CHART)));
+}
+
+/// An authored beatmap, stored as `*.chart.ron` under `assets/charts`.
+///
+/// One song can carry several tracks, e.g. an easier one for a handicap match;
+/// each team plays the track it picked.
+#[derive(Debug, Asset, TypePath, Serialize, Deserialize)]
+pub struct Chart {
+    pub song: Song,
+    pub tracks: Vec<Track>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Track {
+    pub name: String,
+    /// Keys this track uses; the others are ignored while playing it.
+    #[serde(default = "all_lanes")]
+    pub lanes: Vec<i32>,
+    /// Notes sorted by `beat` once loaded.
+    pub notes: Vec<ChartNote>,
+}
+
+fn all_lanes() -> Vec<i32> {
+    LANES.collect()
+}
+
+/// The track a chart is played against.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Song {
+    /// Audio asset path, relative to `assets`.
+    pub music: String,
+    /// `music`, loaded along with the chart.
+    #[serde(skip)]
+    pub audio: Handle<AudioSource>,
+    pub bpm: f32,
+    /// Seconds into the track where beat 0 falls.
+    pub offset: f32,
+    /// `(beats per bar, beat unit)`, e.g. `(4, 4)`.
+    pub time_signature: (u32, u32),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChartNote {
+    /// Position on the song's beat grid, fractions allowed.
+    pub beat: f32,
+    /// Lane, same numbering as `PlayerCommand::Hit1..=Hit3`.
+    pub key: i32,
+    /// Beats the key has to be held for; 0 for a tap note.
+    #[serde(default, skip_serializing_if = "is_tap")]
+    pub length: f32,
+}
+
+fn is_tap(length: &f32) -> bool {
+    *length <= 0.
+}
+
+impl Song {
+    pub fn seconds_per_beat(&self) -> f32 {
+        60. / self.bpm
+    }
+
+    pub fn beats_per_bar(&self) -> u32 {
+        self.time_signature.0.max(1)
+    }
+
+    /// Time from the start of the track to `beat`.
+    pub fn beat_time(&self, beat: f32) -> Duration {
+        Duration::from_secs_f32((self.offset + beat * self.seconds_per_beat()).max(0.))
+    }
+}
+
+impl Chart {
+    pub fn note_time(&self, note: &ChartNote) -> Duration {
+        self.song.beat_time(note.beat)
+    }
+
+    /// When a hold note should be released, `None` for tap notes.
+    pub fn note_end_time(&self, note: &ChartNote) -> Option<Duration> {
+        (!is_tap(&note.length)).then(|| self.song.beat_time(note.beat + note.length))
+    }
+
+    /// The track at `index`, falling back to the last one.
+    pub fn track(&self, index: usize) -> &Track {
+        &self.tracks[index.min(self.tracks.len() - 1)]
+    }
+
+    /// Render as `*.chart.ron` text, one note per line.
+    pub fn to_ron(&self) -> ron::Result<String> {
+        ron::ser::to_string_pretty(self, PrettyConfig::new().depth_limit(4))
+    }
+
+    /// Beat where the last note of any track ends, or 0 for an empty chart.
+    pub fn last_beat(&self) -> f32 {
+        self.tracks
+            .iter()
+            .flat_map(|track| &track.notes)
+            .map(|note| note.beat + note.length)
+            .fold(0., f32::max)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ChartLoader;
+
+#[derive(Debug, Error)]
+pub enum ChartLoaderError {
+    #[error("could not read chart: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse chart: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    #[error("chart has a bpm of {0}, it has to be above 0")]
+    Bpm(f32),
+    #[error("chart has an offset of {0}, it has to be a number of seconds")]
+    Offset(f32),
+    #[error("chart has no tracks")]
+    NoTracks,
+    #[error("track {track:?} has lanes {lanes:?}, it needs at least one and each from 1 to 3")]
+    Lanes { track: String, lanes: Vec<i32> },
+    #[error("track {track:?} has a note at beat {beat} held for {length}, it has to lie within the first hour of the song")]
+    Note {
+        track: String,
+        beat: f32,
+        length: f32,
+    },
+    #[error("track {track:?} has a note in lane {key}, which is not one of its lanes")]
+    Lane { track: String, key: i32 },
+}
+
+/// Read and check `*.chart.ron` text, sorting each track's notes.
+fn parse_chart(bytes: &[u8]) -> Result<Chart, ChartLoaderError> {
+    let mut chart: Chart = ron::de::from_bytes(bytes)?;
+    // beat times are computed from these, and a Duration can't be infinite
+    if !(chart.song.bpm.is_finite() && chart.song.bpm > 0.) {
+        return Err(ChartLoaderError::Bpm(chart.song.bpm));
+    }
+    if !chart.song.offset.is_finite() {
+        return Err(ChartLoaderError::Offset(chart.song.offset));
+    }
+    if chart.tracks.is_empty() {
+        return Err(ChartLoaderError::NoTracks);
+    }
+    for track in &mut chart.tracks {
+        if track.lanes.is_empty() || !track.lanes.iter().all(|lane| LANES.contains(lane)) {
+            return Err(ChartLoaderError::Lanes {
+                track: track.name.clone(),
+                lanes: track.lanes.clone(),
+            });
+        }
+        if let Some(note) = track.notes.iter().find(|n| !track.lanes.contains(&n.key)) {
+            return Err(ChartLoaderError::Lane {
+                track: track.name.clone(),
+                key: note.key,
+            });
+        }
+        let song = &chart.song;
+        let in_song = |beat: f32| {
+            beat.is_finite()
+                && beat >= 0.
+                && song.offset + beat * song.seconds_per_beat() <= MAX_SONG_LENGTH
+        };
+        if let Some(note) = track
+            .notes
+            .iter()
+            .find(|n| !(in_song(n.beat) && n.length >= 0. && in_song(n.beat + n.length)))
+        {
+            return Err(ChartLoaderError::Note {
+                track: track.name.clone(),
+                beat: note.beat,
+                length: note.length,
+            });
+        }
+        track.notes.sort_by(|a, b| a.beat.total_cmp(&b.beat));
+    }
+    Ok(chart)
+}
+
+impl AssetLoader for ChartLoader {
+    type Asset = Chart;
+    type Settings = ();
+    type Error = ChartLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Chart, ChartLoaderError>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let mut chart = parse_chart(&bytes)?;
+            chart.song.audio = load_context.load(chart.song.music.clone());
+            Ok(chart)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["chart.ron"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chart_with_note(note: &str) -> String {
+        format!(
+            r#"(
+                song: (music: "sounds/bgm.wav", bpm: 120.0, offset: 0.5, time_signature: (4, 4)),
+                tracks: [(name: "Normal", notes: [(beat: 4.0, key: 1), {note}])],
+            )"#
+        )
+    }
+
+    #[test]
+    fn notes_are_sorted_by_beat() {
+        let chart =
+            parse_chart(chart_with_note("(beat: 2.0, key: 2, length: 1.0)").as_bytes()).unwrap();
+        let beats: Vec<f32> = chart.tracks[0].notes.iter().map(|n| n.beat).collect();
+        assert_eq!(beats, [2., 4.]);
+    }
+
+    #[test]
+    fn notes_off_the_song_are_rejected() {
+        for note in [
+            "(beat: -1.0, key: 1)",
+            "(beat: 1.0, key: 1, length: -1.0)",
+            "(beat: 1e30, key: 1)",
+            "(beat: 1.0, key: 1, length: inf)",
+            "(beat: NaN, key: 1)",
+        ] {
+            let result = parse_chart(chart_with_note(note).as_bytes());
+            assert!(
+                matches!(result, Err(ChartLoaderError::Note { .. })),
+                "{note} gave {result:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use bevy::audio::{Decodable, Source};
+use bevy::prelude::*;
+use bevy::reflect::TypePath;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Song position measured against the wall clock.
+///
+/// Unlike a `Stopwatch` ticked with `Time::delta`, reading this clock does not
+/// depend on the frame rate, and inputs are judged against the time they were
+/// made however long the frame reading them takes.
+#[derive(Debug, Default, Resource)]
+pub struct SongClock {
+    /// Instant at which song position 0 was played, while running.
+    origin: Option<Instant>,
+    /// Position the clock stopped at, while paused.
+    paused_at: Duration,
+}
+
+impl SongClock {
+    /// Start (or resume) counting from the current position.
+    pub fn start(&mut self, now: Instant) {
+        if self.origin.is_none() {
+            self.origin = Some(now - self.paused_at);
+        }
+    }
+
+    pub fn pause(&mut self, now: Instant) {
+        if self.origin.is_some() {
+            self.paused_at = self.position_at(now);
+            self.origin = None;
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Song position at `at`, clamped to 0 for instants before the song started.
+    pub fn position_at(&self, at: Instant) -> Duration {
+        match self.origin {
+            Some(origin) => at.saturating_duration_since(origin),
+            None => self.paused_at,
+        }
+    }
+
+    pub fn now(&self) -> Duration {
+        self.position_at(Instant::now())
+    }
+}
+
+/// A song played with a `Playhead`, so the song clock can follow the audio
+/// rather than only the moment it started.
+#[derive(Asset, TypePath)]
+pub struct SongTrack {
+    audio: AudioSource,
+    playhead: Playhead,
+}
+
+impl SongTrack {
+    pub fn new(audio: AudioSource) -> (Self, Playhead) {
+        let playhead = Playhead::default();
+        let track = Self {
+            audio,
+            playhead: playhead.clone(),
+        };
+        (track, playhead)
+    }
+}
+
+/// How far the audio output has played a `SongTrack`, counted in samples as
+/// the audio thread pulls them.
+#[derive(Debug, Clone, Default, Component)]
+pub struct Playhead(Arc<PlayheadState>);
+
+#[derive(Debug, Default)]
+struct PlayheadState {
+    samples: AtomicU64,
+    /// Samples per second over all channels.
+    rate: AtomicU32,
+}
+
+impl Playhead {
+    /// Song position of the last sample handed to the audio output, `None`
+    /// until the track has started decoding.
+    pub fn position(&self) -> Option<Duration> {
+        let rate = self.0.rate.load(Ordering::Relaxed);
+        let samples = self.0.samples.load(Ordering::Relaxed);
+        (rate > 0).then(|| Duration::from_secs_f64(samples as f64 / rate as f64))
+    }
+}
+
+impl Decodable for SongTrack {
+    type DecoderItem = <AudioSource as Decodable>::DecoderItem;
+    type Decoder = PlayheadDecoder<<AudioSource as Decodable>::Decoder>;
+
+    fn decoder(&self) -> Self::Decoder {
+        let inner = self.audio.decoder();
+        let state = &self.playhead.0;
+        state.samples.store(0, Ordering::Relaxed);
+        state.rate.store(
+            inner.sample_rate() * inner.channels() as u32,
+            Ordering::Relaxed,
+        );
+        PlayheadDecoder {
+            inner,
+            playhead: self.playhead.clone(),
+        }
+    }
+}
+
+/// Passes the samples of a decoder through, moving its `Playhead` along.
+pub struct PlayheadDecoder<D> {
+    inner: D,
+    playhead: Playhead,
+}
+
+impl<D: Iterator> Iterator for PlayheadDecoder<D> {
+    type Item = D::Item;
+
+    fn next(&mut self) -> Option<D::Item> {
+        let sample = self.inner.next()?;
+        self.playhead.0.samples.fetch_add(1, Ordering::Relaxed);
+        Some(sample)
+    }
+}
+
+impl<D: Source> Source for PlayheadDecoder<D>
+where
+    D::Item: bevy::audio::Sample,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
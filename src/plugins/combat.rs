@@ -0,0 +1,212 @@
+use crate::config::{CombatAction, LifeConfig};
+use crate::plugins::character_selection::PlayerSettings;
+use crate::plugins::life::{change_life, LifeGauges};
+use crate::plugins::sound_player::HitResult;
+use crate::plugins::stats::{Judgement, JudgementEvent};
+use crate::plugins::team_side;
+use crate::plugins::tug::TugOfWar;
+use crate::AppState;
+use bevy::{log, prelude::*};
+
+/// Sequences from the design notes. `A`, `B` and `C` are on-beat hits in
+/// lanes 1, 2 and 3; `DDDD`, four Perfects in a row, counts when they spell
+/// none of the others.
+const SEQUENCES: [(&str, CombatAction); 4] = [
+    ("AAAB", CombatAction::Advance),
+    ("BBAB", CombatAction::Attack),
+    ("CCAB", CombatAction::Defense),
+    ("BABA", CombatAction::Dodge),
+];
+const SEQUENCE_LEN: usize = 4;
+const ADVANCE_PULL: f32 = 0.08;
+const ATTACK_DAMAGE: f32 = 12.;
+/// A dodged attack lets the defender pull back this much.
+const DODGE_PULL: f32 = 0.04;
+const SKILL_DAMAGE: f32 = 20.;
+const SKILL_PULL: f32 = 0.1;
+
+/// Turn each team's on-beat hits into combat actions and play them out.
+#[derive(Debug)]
+pub struct CombatPlugin;
+
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<CombatEvent>()
+            .init_resource::<Combat>()
+            .add_systems(OnEnter(AppState::InGame), reset_combat)
+            .add_systems(
+                Update,
+                (sequence_system, combat_effect_system)
+                    .chain()
+                    .run_if(in_state(AppState::InGame)),
+            );
+    }
+}
+
+#[derive(Debug, Event)]
+pub struct CombatEvent {
+    pub team: i32,
+    pub action: CombatAction,
+}
+
+#[derive(Debug, Default, Resource)]
+struct Combat(Vec<TeamCombat>);
+
+impl Combat {
+    fn get_mut(&mut self, team: i32) -> Option<&mut TeamCombat> {
+        self.0.get_mut((team - 1) as usize)
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct TeamCombat {
+    /// Latest on-beat hits as `(letter, perfect)`, oldest first.
+    sequence: Vec<(char, bool)>,
+    /// Defense or dodge waiting for the next attack.
+    guard: Option<CombatAction>,
+}
+
+impl TeamCombat {
+    /// Add a hit and return the action its sequence completes, if any.
+    fn push(&mut self, letter: char, perfect: bool) -> Option<CombatAction> {
+        self.sequence.push((letter, perfect));
+        if self.sequence.len() > SEQUENCE_LEN {
+            self.sequence.remove(0);
+        }
+        if self.sequence.len() < SEQUENCE_LEN {
+            return None;
+        }
+
+        let letters: String = self.sequence.iter().map(|(letter, _)| letter).collect();
+        let action = SEQUENCES
+            .iter()
+            .find(|(sequence, _)| *sequence == letters)
+            .map(|(_, action)| *action)
+            .or_else(|| {
+                let all_perfect = self.sequence.iter().all(|(_, perfect)| *perfect);
+                all_perfect.then_some(CombatAction::Skill)
+            });
+        if action.is_some() {
+            self.sequence.clear();
+        }
+        action
+    }
+}
+
+fn reset_combat(mut combat: ResMut<Combat>, settings: Res<PlayerSettings>) {
+    combat.0 = vec![TeamCombat::default(); settings.teams().count()];
+}
+
+fn sequence_system(
+    mut judgement_evt: EventReader<JudgementEvent>,
+    mut combat: ResMut<Combat>,
+    mut combat_evt_w: EventWriter<CombatEvent>,
+) {
+    for e in judgement_evt.read() {
+        let Some(team_combat) = combat.get_mut(e.team) else {
+            continue;
+        };
+
+        match (e.judgement, e.offset) {
+            // a missed or wrong note breaks the sequence
+            (Judgement::Hit(HitResult::Miss) | Judgement::WrongKey, _) => {
+                team_combat.sequence.clear();
+            }
+            // only presses count, not hold releases
+            (Judgement::Hit(result), Some(_)) => {
+                let letter = match e.key {
+                    1 => 'A',
+                    2 => 'B',
+                    _ => 'C',
+                };
+                if let Some(action) = team_combat.push(letter, result == HitResult::Perfect) {
+                    log::info!(team = e.team, %action, "combat action");
+                    combat_evt_w.send(CombatEvent {
+                        team: e.team,
+                        action,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn combat_effect_system(
+    mut combat_evt: EventReader<CombatEvent>,
+    mut combat: ResMut<Combat>,
+    mut tug: ResMut<TugOfWar>,
+    mut gauges: ResMut<LifeGauges>,
+    settings: Res<PlayerSettings>,
+    life_config: Res<LifeConfig>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    for e in combat_evt.read() {
+        // teammates play on the same side of the screen
+        let opponents = settings
+            .teams()
+            .filter(|team| team_side(*team) != team_side(e.team));
+        match e.action {
+            CombatAction::Advance => tug.pull(e.team, ADVANCE_PULL),
+            CombatAction::Defense | CombatAction::Dodge => {
+                if let Some(team_combat) = combat.get_mut(e.team) {
+                    team_combat.guard = Some(e.action);
+                }
+            }
+            CombatAction::Attack => {
+                for opponent in opponents {
+                    let guard = combat
+                        .get_mut(opponent)
+                        .and_then(|team_combat| team_combat.guard.take());
+                    match guard {
+                        Some(CombatAction::Dodge) => {
+                            log::debug!(team = opponent, "attack dodged");
+                            tug.pull(opponent, DODGE_PULL);
+                        }
+                        Some(_) => log::debug!(team = opponent, "attack blocked"),
+                        None => {
+                            if let Some(gauge) = gauges.get_mut(opponent) {
+                                let damage = -ATTACK_DAMAGE;
+                                change_life(gauge, opponent, damage, &life_config, &mut next_state);
+                            }
+                        }
+                    }
+                }
+            }
+            // cannot be blocked or dodged
+            CombatAction::Skill => {
+                for opponent in opponents {
+                    if let Some(gauge) = gauges.get_mut(opponent) {
+                        let damage = -SKILL_DAMAGE;
+                        change_life(gauge, opponent, damage, &life_config, &mut next_state);
+                    }
+                }
+                tug.pull(e.team, SKILL_PULL);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(hits: &str, perfect: bool) -> Vec<CombatAction> {
+        let mut team_combat = TeamCombat::default();
+        hits.chars()
+            .filter_map(|letter| team_combat.push(letter, perfect))
+            .collect()
+    }
+
+    #[test]
+    fn perfect_sequences_keep_their_action() {
+        assert_eq!(play("AAAB", true), [CombatAction::Advance]);
+        assert_eq!(play("BBAB", false), [CombatAction::Attack]);
+    }
+
+    #[test]
+    fn other_perfect_hits_are_a_skill() {
+        assert_eq!(play("ABCC", true), [CombatAction::Skill]);
+        assert_eq!(play("ABCC", false), []);
+    }
+}
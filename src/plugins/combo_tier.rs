@@ -0,0 +1,153 @@
+use crate::plugins::character_selection::PlayerSettings;
+use crate::plugins::score::{scoreboard_style, ComboEvent, ComboNumber, ScoreSetting};
+use crate::plugins::{team_row, team_side};
+use crate::AppState;
+use bevy::audio::{PlaybackMode, Volume};
+use bevy::{log, prelude::*};
+use bevy_tweening::{
+    lens::{SpriteColorLens, TransformScaleLens},
+    Animator, EaseFunction, Tween,
+};
+use std::time::Duration;
+
+const PLATE_SIZE: Vec2 = Vec2::new(356., 78.);
+/// Where the tier-up art bursts out, on the team's half of the screen.
+const CELEBRATION_POS: Vec2 = Vec2::new(620., 300.);
+const CELEBRATION_ROW_GAP: f32 = 130.;
+const CELEBRATION_DURATION: Duration = Duration::from_millis(900);
+
+/// Combo tiers: a plate behind the combo counter once a tier is reached, a
+/// burst of the combo art on every new tier and a cue when the combo breaks.
+#[derive(Debug)]
+pub struct ComboTierPlugin;
+
+impl Plugin for ComboTierPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(AppState::InGame), setup_combo_plates)
+            .add_systems(
+                Update,
+                (combo_plate_system, combo_event_system).run_if(in_state(AppState::InGame)),
+            )
+            .add_systems(OnExit(AppState::InGame), cleanup_combo_tier);
+    }
+}
+
+#[derive(Debug, Component)]
+struct ComboTierUi;
+
+#[derive(Debug, Component)]
+struct ComboPlate {
+    team: i32,
+}
+
+#[derive(Resource)]
+struct ComboBreakSound(Handle<AudioSource>);
+
+fn setup_combo_plates(
+    mut commands: Commands,
+    settings: Res<PlayerSettings>,
+    asset_server: Res<AssetServer>,
+) {
+    commands.insert_resource(ComboBreakSound(asset_server.load("sounds/gong.ogg")));
+
+    let plate = asset_server.load("images/ui/game/COMBO_0.png");
+    for team in settings.teams() {
+        commands.spawn((
+            ImageBundle {
+                image: UiImage::new(plate.clone()),
+                style: Style {
+                    width: Val::Px(PLATE_SIZE.x),
+                    height: Val::Px(PLATE_SIZE.y),
+                    ..scoreboard_style(team, 75.)
+                },
+                // under the combo counter
+                z_index: ZIndex::Global(-1),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            ComboPlate { team },
+            ComboTierUi,
+        ));
+    }
+}
+
+fn combo_plate_system(
+    combo: Res<ComboNumber>,
+    setting: Res<ScoreSetting>,
+    mut query: Query<(&ComboPlate, &mut Visibility)>,
+) {
+    for (plate, mut visibility) in &mut query {
+        *visibility = if setting.tier(combo.get(plate.team)) > 0 {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
+
+fn combo_event_system(
+    mut commands: Commands,
+    mut combo_evt: EventReader<ComboEvent>,
+    break_sound: Res<ComboBreakSound>,
+    asset_server: Res<AssetServer>,
+) {
+    for e in combo_evt.read() {
+        match *e {
+            ComboEvent::Tier { team, tier } => {
+                let pos = Vec2::new(
+                    team_side(team) * CELEBRATION_POS.x,
+                    CELEBRATION_POS.y - team_row(team) as f32 * CELEBRATION_ROW_GAP,
+                );
+                // each tier bursts out bigger than the last
+                let size = 1. + tier as f32 * 0.25;
+                let scale_tween = Tween::new(
+                    EaseFunction::BackOut,
+                    CELEBRATION_DURATION,
+                    TransformScaleLens {
+                        start: Vec3::splat(size * 0.5),
+                        end: Vec3::splat(size),
+                    },
+                );
+                // despawned by `JumpImagePlugin` once faded out
+                let color_tween = Tween::new(
+                    EaseFunction::QuadraticIn,
+                    CELEBRATION_DURATION,
+                    SpriteColorLens {
+                        start: Color::WHITE,
+                        end: Color::WHITE.with_a(0.),
+                    },
+                )
+                .with_completed_event(0);
+                commands.spawn((
+                    SpriteBundle {
+                        texture: asset_server.load("images/ui/game/COMBO_1.png"),
+                        transform: Transform::from_translation(pos.extend(45.)),
+                        ..default()
+                    },
+                    Animator::new(scale_tween),
+                    Animator::new(color_tween),
+                    ComboTierUi,
+                ));
+            }
+            ComboEvent::Break { team, combo } => {
+                log::debug!(team, combo, "combo break");
+                // the gong, slowed down into a thud
+                commands.spawn(AudioBundle {
+                    source: break_sound.0.clone(),
+                    settings: PlaybackSettings {
+                        mode: PlaybackMode::Despawn,
+                        volume: Volume::new_relative(1.5),
+                        speed: 0.5,
+                        ..default()
+                    },
+                });
+            }
+        }
+    }
+}
+
+fn cleanup_combo_tier(mut commands: Commands, query: Query<Entity, With<ComboTierUi>>) {
+    for ent in &query {
+        commands.entity(ent).despawn();
+    }
+}
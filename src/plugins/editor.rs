@@ -0,0 +1,546 @@
+use crate::plugins::chart::{Chart, ChartNote, SelectedChart, Song, Track};
+use crate::plugins::clock::SongClock;
+use crate::plugins::input::{PlayerCommand, PlayerCommandEvent};
+use crate::plugins::sound_player::{beat_image, sync_song_clock, Music};
+use crate::AppState;
+use bevy::asset::io::file::FileAssetReader;
+use bevy::{log, prelude::*, sprite::Anchor};
+use std::time::Duration;
+
+const PIXELS_PER_SECOND: f32 = 300.;
+const VIEW_HALF_WIDTH: f32 = 960.;
+const LANE_HEIGHT: f32 = 70.;
+const NOTE_SCALE: f32 = 0.5;
+/// Beat subdivisions the grid can snap to.
+const GRID_DIVISIONS: [u32; 5] = [1, 2, 3, 4, 8];
+const TEXT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+const HELP: &str = "Space play/pause  R restart  taps record notes\n\
+    Left/Right scrub (paused)  Ctrl+Left/Right move notes  Del delete\n\
+    1/2 track in P1/P2 row  [ ] grid  Q quantize  Ctrl+S save  M menu";
+
+/// Record, edit and save charts against their song.
+///
+/// Two tracks are shown at a time, one per row; each row is recorded with one
+/// team's keys.
+///
+/// The music cannot be seeked, so scrubbing only moves the edit cursor; resuming
+/// playback continues from where the music was paused.
+#[derive(Debug)]
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(AppState::Editor), setup_editor)
+            .add_systems(OnExit(AppState::Editor), cleanup_editor)
+            .add_systems(
+                Update,
+                (
+                    load_editor_chart,
+                    editor_controls,
+                    sync_song_clock,
+                    record_taps,
+                    draw_timeline,
+                    status_text_update_system,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Editor)),
+            );
+    }
+}
+
+#[derive(Debug, Resource)]
+struct Editor {
+    /// Working copy of the chart, `None` until it has loaded.
+    song: Option<Song>,
+    tracks: Vec<Track>,
+    /// Track shown in each row; row `n` is recorded with team `n + 1`'s keys.
+    rows: [usize; 2],
+    /// Song position being edited; follows the music while it plays.
+    cursor: Duration,
+    /// Index into `GRID_DIVISIONS`.
+    grid: usize,
+    dirty: bool,
+    /// M was pressed with unsaved edits; pressing it again leaves anyway.
+    leaving: bool,
+}
+
+impl Editor {
+    fn grid_step(&self) -> f32 {
+        1. / GRID_DIVISIONS[self.grid] as f32
+    }
+
+    fn beat_at(song: &Song, time: Duration) -> f32 {
+        (time.as_secs_f32() - song.offset) / song.seconds_per_beat()
+    }
+
+    /// Whether `note` sits on the grid cell under the cursor.
+    fn is_at_cursor(&self, song: &Song, note: &ChartNote) -> bool {
+        (note.beat - Self::beat_at(song, self.cursor)).abs() < self.grid_step() / 2.
+    }
+
+    /// Move the cursor `steps` grid cells, snapping it to the grid.
+    fn step_cursor(&mut self, song: &Song, steps: f32) {
+        let step = self.grid_step();
+        let beat = (Self::beat_at(song, self.cursor) / step).round() + steps;
+        self.cursor = song.beat_time(beat * step);
+    }
+
+    /// Tracks in at least one row, each listed once.
+    fn shown_tracks(&self) -> Vec<usize> {
+        let mut shown = self.rows.to_vec();
+        shown.dedup();
+        shown
+    }
+
+    fn note_count(&self) -> usize {
+        self.tracks.iter().map(|track| track.notes.len()).sum()
+    }
+}
+
+fn sort_notes(track: &mut Track) {
+    track
+        .notes
+        .sort_by(|a, b| a.beat.total_cmp(&b.beat).then(a.key.cmp(&b.key)));
+    track
+        .notes
+        .dedup_by(|a, b| a.beat == b.beat && a.key == b.key);
+}
+
+#[derive(Debug, Component)]
+struct EditorTag;
+
+/// Redrawn from scratch every frame.
+#[derive(Debug, Component)]
+struct TimelineSprite;
+
+#[derive(Debug, Component)]
+struct StatusText;
+
+fn setup_editor(mut commands: Commands, mut clock: ResMut<SongClock>) {
+    clock.reset();
+    commands.insert_resource(Editor {
+        song: None,
+        tracks: Vec::new(),
+        rows: [0, 0],
+        cursor: Duration::ZERO,
+        grid: 1,
+        dirty: false,
+        leaving: false,
+    });
+
+    let text_style = TextStyle {
+        font_size: 30.,
+        color: TEXT_COLOR,
+        ..default()
+    };
+    commands.spawn((
+        TextBundle::from_sections([
+            TextSection::new("EDITOR  ", text_style.clone()),
+            TextSection::from_style(text_style.clone()),
+        ])
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(5.),
+            left: Val::Px(5.),
+            ..default()
+        }),
+        StatusText,
+        EditorTag,
+    ));
+    commands.spawn((
+        TextBundle::from_section(HELP, text_style).with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(5.),
+            left: Val::Px(5.),
+            ..default()
+        }),
+        EditorTag,
+    ));
+}
+
+fn cleanup_editor(
+    mut commands: Commands,
+    query: Query<Entity, With<EditorTag>>,
+    timeline_query: Query<Entity, With<TimelineSprite>>,
+    music_query: Query<Entity, With<Music>>,
+    mut clock: ResMut<SongClock>,
+) {
+    for ent in query.iter().chain(&timeline_query).chain(&music_query) {
+        commands.entity(ent).despawn_recursive();
+    }
+    commands.remove_resource::<Editor>();
+    clock.reset();
+}
+
+fn spawn_music(commands: &mut Commands, asset_server: &AssetServer, song: &Song, paused: bool) {
+    commands.spawn((
+        AudioBundle {
+            source: asset_server.load(&song.music),
+            settings: PlaybackSettings {
+                paused,
+                ..PlaybackSettings::ONCE
+            },
+        },
+        Music,
+    ));
+}
+
+fn load_editor_chart(
+    mut commands: Commands,
+    mut editor: ResMut<Editor>,
+    selected_chart: Res<SelectedChart>,
+    charts: Res<Assets<Chart>>,
+    asset_server: Res<AssetServer>,
+) {
+    if editor.song.is_some() {
+        return;
+    }
+    let Some(chart) = charts.get(&selected_chart.0) else {
+        return;
+    };
+
+    spawn_music(&mut commands, &asset_server, &chart.song, true);
+    editor.song = Some(chart.song.clone());
+    editor.tracks = chart.tracks.clone();
+    editor.rows = [0, 1.min(chart.tracks.len() - 1)];
+}
+
+#[allow(clippy::too_many_arguments)]
+fn editor_controls(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    mut editor: ResMut<Editor>,
+    mut clock: ResMut<SongClock>,
+    music_query: Query<(Entity, &AudioSink), With<Music>>,
+    selected_chart: Res<SelectedChart>,
+    asset_server: Res<AssetServer>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let Some(song) = editor.song.clone() else {
+        return;
+    };
+    let ctrl = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    let playing = music_query
+        .get_single()
+        .is_ok_and(|(_, sink)| !sink.is_paused());
+
+    if keys.just_pressed(KeyCode::M) {
+        if !editor.dirty || editor.leaving {
+            next_state.set(AppState::Menu);
+            return;
+        }
+        editor.leaving = true;
+    } else if keys.get_just_pressed().next().is_some() {
+        editor.leaving = false;
+    }
+
+    if keys.just_pressed(KeyCode::Space) {
+        if let Ok((_, sink)) = music_query.get_single() {
+            sink.toggle();
+        }
+    }
+
+    if keys.just_pressed(KeyCode::R) {
+        for (ent, _) in &music_query {
+            commands.entity(ent).despawn();
+        }
+        clock.reset();
+        editor.cursor = Duration::ZERO;
+        spawn_music(&mut commands, &asset_server, &song, false);
+    }
+
+    if keys.just_pressed(KeyCode::BracketLeft) {
+        editor.grid = editor.grid.saturating_sub(1);
+    }
+    if keys.just_pressed(KeyCode::BracketRight) {
+        editor.grid = (editor.grid + 1).min(GRID_DIVISIONS.len() - 1);
+    }
+
+    for (row, key_code) in [KeyCode::Key1, KeyCode::Key2].into_iter().enumerate() {
+        if keys.just_pressed(key_code) {
+            editor.rows[row] = (editor.rows[row] + 1) % editor.tracks.len();
+        }
+    }
+
+    if keys.just_pressed(KeyCode::Q) {
+        let step = editor.grid_step();
+        for track in &mut editor.tracks {
+            for note in &mut track.notes {
+                note.beat = (note.beat / step).round() * step;
+                note.length = (note.length / step).round() * step;
+            }
+            sort_notes(track);
+        }
+        editor.dirty = true;
+    }
+
+    if ctrl && keys.just_pressed(KeyCode::S) {
+        save_chart(&mut editor, &song, &selected_chart.0, &asset_server);
+    }
+
+    // the notes under the cursor can only be edited while the music is paused
+    if playing {
+        return;
+    }
+
+    let direction = if keys.just_pressed(KeyCode::Left) {
+        -1.
+    } else if keys.just_pressed(KeyCode::Right) {
+        1.
+    } else {
+        0.
+    };
+    let delete = keys.any_just_pressed([KeyCode::Delete, KeyCode::Back]);
+    let shift = if ctrl { direction } else { 0. };
+    if delete || shift != 0. {
+        let step = editor.grid_step() * shift;
+        for index in editor.shown_tracks() {
+            let mut track = std::mem::replace(&mut editor.tracks[index], empty_track());
+            let before = track.notes.len();
+            if delete {
+                track.notes.retain(|note| !editor.is_at_cursor(&song, note));
+            }
+            for note in &mut track.notes {
+                if editor.is_at_cursor(&song, note) {
+                    note.beat += step;
+                }
+            }
+            sort_notes(&mut track);
+            editor.dirty |= shift != 0. || track.notes.len() != before;
+            editor.tracks[index] = track;
+        }
+    }
+    if direction != 0. {
+        editor.step_cursor(&song, direction);
+    }
+}
+
+fn empty_track() -> Track {
+    Track {
+        name: String::new(),
+        lanes: Vec::new(),
+        notes: Vec::new(),
+    }
+}
+
+/// Write the edited chart back over the file `handle` was loaded from.
+fn save_chart(
+    editor: &mut Editor,
+    song: &Song,
+    handle: &Handle<Chart>,
+    asset_server: &AssetServer,
+) {
+    let Some(asset_path) = asset_server.get_path(handle.id()) else {
+        log::error!("could not save chart: it was not loaded from a file");
+        return;
+    };
+    let chart = Chart {
+        song: song.clone(),
+        tracks: editor.tracks.clone(),
+    };
+    let path = FileAssetReader::get_base_path()
+        .join("assets")
+        .join(asset_path.path());
+
+    let result = chart
+        .to_ron()
+        .map_err(|e| e.to_string())
+        .and_then(|text| std::fs::write(&path, text).map_err(|e| e.to_string()));
+    match result {
+        Ok(()) => {
+            log::info!(path = ?path, notes = editor.note_count(), "chart saved");
+            editor.dirty = false;
+            // pick up the new notes in the next match
+            asset_server.reload(asset_path);
+        }
+        Err(e) => log::error!(path = ?path, "could not save chart: {e}"),
+    }
+}
+
+fn record_taps(
+    mut player_command_evt: EventReader<PlayerCommandEvent>,
+    mut editor: ResMut<Editor>,
+    clock: Res<SongClock>,
+    music_query: Query<&AudioSink, With<Music>>,
+) {
+    let Some(song) = editor.song.clone() else {
+        return;
+    };
+    let playing = music_query.get_single().is_ok_and(|sink| !sink.is_paused());
+    if playing {
+        editor.cursor = clock.now();
+    }
+
+    for e in player_command_evt.read() {
+        let key = match e.cmd {
+            PlayerCommand::Hit1 => 1,
+            PlayerCommand::Hit2 => 2,
+            PlayerCommand::Hit3 => 3,
+            PlayerCommand::Ex | PlayerCommand::Exit => {
+                continue;
+            }
+        };
+        let Some(&index) = editor.rows.get((e.team - 1) as usize) else {
+            continue;
+        };
+        // while paused, taps place notes at the cursor
+        let time = if playing { e.time } else { editor.cursor };
+        let beat = Editor::beat_at(&song, time);
+        let min_length = editor.grid_step();
+        let track = &mut editor.tracks[index];
+
+        if !e.pressed {
+            // holding a key for longer than a grid cell records a hold note
+            let note = track
+                .notes
+                .iter_mut()
+                .rev()
+                .find(|note| note.key == key && note.beat <= beat);
+            if let Some(note) = note.filter(|note| playing && beat - note.beat >= min_length) {
+                note.length = beat - note.beat;
+                editor.dirty = true;
+            }
+            continue;
+        }
+
+        if !track.lanes.contains(&key) {
+            track.lanes.push(key);
+            track.lanes.sort();
+        }
+        track.notes.push(ChartNote {
+            beat,
+            key,
+            length: 0.,
+        });
+        sort_notes(track);
+        editor.dirty = true;
+    }
+}
+
+fn lane_y(team: i32, key: i32) -> f32 {
+    let top = if team == 1 { 250. } else { -50. };
+    top - (key - 1) as f32 * LANE_HEIGHT
+}
+
+fn draw_timeline(
+    mut commands: Commands,
+    editor: Res<Editor>,
+    old_sprites: Query<Entity, With<TimelineSprite>>,
+    asset_server: Res<AssetServer>,
+) {
+    for ent in &old_sprites {
+        commands.entity(ent).despawn();
+    }
+    let Some(song) = &editor.song else {
+        return;
+    };
+
+    let cursor = editor.cursor.as_secs_f32();
+    let x_of = |beat: f32| (song.beat_time(beat).as_secs_f32() - cursor) * PIXELS_PER_SECOND;
+    let mut line = |x: f32, width: f32, color: Color| {
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color,
+                    custom_size: Some(Vec2::new(width, 560.)),
+                    ..default()
+                },
+                transform: Transform::from_xyz(x, 60., 0.),
+                ..default()
+            },
+            TimelineSprite,
+        ));
+    };
+
+    // grid lines, brighter on each bar
+    let step = editor.grid_step();
+    let view_beats = VIEW_HALF_WIDTH / PIXELS_PER_SECOND / song.seconds_per_beat();
+    let cursor_beat = Editor::beat_at(song, editor.cursor);
+    let first = ((cursor_beat - view_beats) / step).floor() as i32;
+    let last = ((cursor_beat + view_beats) / step).ceil() as i32;
+    for i in first..=last {
+        let beat = i as f32 * step;
+        if beat < 0. {
+            continue;
+        }
+        let (width, color) = if beat % song.beats_per_bar() as f32 == 0. {
+            (3., Color::rgba(1., 1., 1., 0.6))
+        } else if beat.fract() == 0. {
+            (2., Color::rgba(1., 1., 1., 0.3))
+        } else {
+            (1., Color::rgba(1., 1., 1., 0.12))
+        };
+        line(x_of(beat), width, color);
+    }
+    line(0., 4., Color::rgb(1., 0.2, 0.2));
+
+    let rows = editor.rows.iter().enumerate();
+    let notes = rows.flat_map(|(row, index)| {
+        let team = row as i32 + 1;
+        editor.tracks[*index]
+            .notes
+            .iter()
+            .map(move |note| (team, note))
+    });
+    for (team, note) in notes {
+        let x = x_of(note.beat);
+        if x.abs() > VIEW_HALF_WIDTH {
+            continue;
+        }
+        let color = if editor.is_at_cursor(song, note) {
+            Color::rgb(1., 1., 0.3)
+        } else {
+            Color::WHITE
+        };
+        if note.length > 0. {
+            let length = x_of(note.beat + note.length) - x;
+            commands.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: color.with_a(0.5),
+                        anchor: Anchor::CenterLeft,
+                        custom_size: Some(Vec2::new(length, LANE_HEIGHT * 0.5)),
+                        ..default()
+                    },
+                    transform: Transform::from_xyz(x, lane_y(team, note.key), 5.),
+                    ..default()
+                },
+                TimelineSprite,
+            ));
+        }
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite { color, ..default() },
+                texture: asset_server.load(beat_image(team, note.key)),
+                transform: Transform {
+                    translation: Vec3::new(x, lane_y(team, note.key), 10.),
+                    scale: Vec3::splat(NOTE_SCALE),
+                    ..default()
+                },
+                ..default()
+            },
+            TimelineSprite,
+        ));
+    }
+}
+
+fn status_text_update_system(editor: Res<Editor>, mut query: Query<&mut Text, With<StatusText>>) {
+    let Some(song) = &editor.song else {
+        return;
+    };
+    for mut text in &mut query {
+        text.sections[1].value = format!(
+            "beat {:.2}  grid 1/{}  P1 row: {}  P2 row: {}  notes {}{}",
+            Editor::beat_at(song, editor.cursor),
+            GRID_DIVISIONS[editor.grid],
+            editor.tracks[editor.rows[0]].name,
+            editor.tracks[editor.rows[1]].name,
+            editor.note_count(),
+            match (editor.dirty, editor.leaving) {
+                (true, true) => "  (unsaved, M again to leave)",
+                (true, false) => "  (unsaved)",
+                (false, _) => "",
+            },
+        );
+    }
+}
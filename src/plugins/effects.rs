@@ -0,0 +1,191 @@
+use crate::config::ImageKey;
+use crate::plugins::character_selection::PlayerSettings;
+use crate::plugins::clock::SongClock;
+use crate::plugins::score::ComboNumber;
+use crate::plugins::{team_side, JumpImage};
+use crate::AppState;
+use bevy::{log, prelude::*};
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::time::Duration;
+
+/// Every this many combo a team sets off a special effect.
+const COMBO_MILESTONE: usize = 10;
+const JUMP_IMAGE_HEIGHT: f32 = 200.;
+
+/// Meme attacks set off by combo milestones, each flying its `ImageKey` image
+/// across the screen.
+#[derive(Debug)]
+pub struct SpecialEffectPlugin;
+
+impl Plugin for SpecialEffectPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DecoyEvent>()
+            .insert_resource(MatchSeed(thread_rng().gen()))
+            .init_resource::<SpecialEffects>()
+            .add_systems(OnEnter(AppState::InGame), reset_effects)
+            .add_systems(
+                Update,
+                trigger_effect_system.run_if(in_state(AppState::InGame)),
+            );
+    }
+}
+
+/// Seed of everything random that affects play, so a match can be replayed.
+#[derive(Debug, Clone, Copy, Resource)]
+pub struct MatchSeed(pub u64);
+
+/// Who an effect lands on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    Own,
+    Opponent,
+}
+
+/// What an effect does while it lasts:
+///
+/// - `WhyHaRuHiKaGe`: the opponent's notes fade to almost invisible.
+/// - `MaZu`: misses do not break the team's combo.
+/// - `Monkey`: the opponent's notes come from the far side of their ring.
+/// - `NetArmy`: the opponent's lanes fill with decoy notes that break their
+///   combo when hit.
+/// - `GenShinStart`: the team's hits pull the rope twice as hard.
+fn effect_spec(key: ImageKey) -> (Target, Duration, Duration) {
+    let (target, duration, cooldown) = match key {
+        ImageKey::WhyHaRuHiKaGe => (Target::Opponent, 6, 20),
+        ImageKey::MaZu => (Target::Own, 8, 25),
+        ImageKey::Monkey => (Target::Opponent, 6, 25),
+        ImageKey::NetArmy => (Target::Opponent, 5, 30),
+        ImageKey::GenShinStart => (Target::Own, 8, 30),
+    };
+    (
+        target,
+        Duration::from_secs(duration),
+        Duration::from_secs(cooldown),
+    )
+}
+
+const EFFECTS: [ImageKey; 5] = [
+    ImageKey::WhyHaRuHiKaGe,
+    ImageKey::MaZu,
+    ImageKey::Monkey,
+    ImageKey::NetArmy,
+    ImageKey::GenShinStart,
+];
+
+/// Ask for decoy notes in `team`'s lanes until `until`.
+#[derive(Debug, Event)]
+pub struct DecoyEvent {
+    pub team: i32,
+    pub until: Duration,
+}
+
+#[derive(Debug, Resource)]
+pub struct SpecialEffects {
+    /// `(affected team, effect, song position it wears off at)`.
+    active: Vec<(i32, ImageKey, Duration)>,
+    /// `(team that set it off, effect, song position it can go off again at)`.
+    cooldowns: Vec<(i32, ImageKey, Duration)>,
+    /// Last combo milestone each team reached.
+    milestones: Vec<usize>,
+    rng: StdRng,
+}
+
+impl Default for SpecialEffects {
+    fn default() -> Self {
+        Self {
+            active: Vec::new(),
+            cooldowns: Vec::new(),
+            milestones: Vec::new(),
+            rng: StdRng::seed_from_u64(0),
+        }
+    }
+}
+
+impl SpecialEffects {
+    /// Whether `key` is affecting `team` at song position `now`.
+    pub fn is_active(&self, team: i32, key: ImageKey, now: Duration) -> bool {
+        self.active
+            .iter()
+            .any(|(t, k, until)| *t == team && *k == key && now < *until)
+    }
+
+    fn is_ready(&self, team: i32, key: ImageKey, now: Duration) -> bool {
+        !self
+            .cooldowns
+            .iter()
+            .any(|(t, k, ready_at)| *t == team && *k == key && now < *ready_at)
+    }
+}
+
+fn reset_effects(
+    mut effects: ResMut<SpecialEffects>,
+    settings: Res<PlayerSettings>,
+    seed: Res<MatchSeed>,
+) {
+    *effects = SpecialEffects {
+        milestones: vec![0; settings.teams().count()],
+        rng: StdRng::seed_from_u64(seed.0),
+        ..default()
+    };
+}
+
+fn trigger_effect_system(
+    mut commands: Commands,
+    mut effects: ResMut<SpecialEffects>,
+    mut decoy_evt_w: EventWriter<DecoyEvent>,
+    combo: Res<ComboNumber>,
+    settings: Res<PlayerSettings>,
+    clock: Res<SongClock>,
+) {
+    let now = clock.now();
+    effects.active.retain(|(.., until)| now < *until);
+    effects.cooldowns.retain(|(.., ready_at)| now < *ready_at);
+
+    for team in settings.teams() {
+        let milestone = combo.get(team) / COMBO_MILESTONE;
+        let index = (team - 1) as usize;
+        // a broken combo starts counting milestones again
+        if milestone <= effects.milestones[index] {
+            effects.milestones[index] = milestone;
+            continue;
+        }
+        effects.milestones[index] = milestone;
+
+        let ready: Vec<_> = EFFECTS
+            .into_iter()
+            .filter(|key| effects.is_ready(team, *key, now))
+            .collect();
+        let Some(&key) = ready.choose(&mut effects.rng) else {
+            continue;
+        };
+
+        let (target, duration, cooldown) = effect_spec(key);
+        let targets: Vec<_> = match target {
+            Target::Own => vec![team],
+            // teammates play on the same side of the screen
+            Target::Opponent => settings
+                .teams()
+                .filter(|t| team_side(*t) != team_side(team))
+                .collect(),
+        };
+        log::info!(team, ?key, ?targets, "special effect");
+        effects.cooldowns.push((team, key, now + cooldown));
+        for target in targets {
+            effects.active.push((target, key, now + duration));
+            if key == ImageKey::NetArmy {
+                decoy_evt_w.send(DecoyEvent {
+                    team: target,
+                    until: now + duration,
+                });
+            }
+
+            // fly from the team's side to the side it lands on
+            commands.spawn(JumpImage {
+                key,
+                from: Vec2::new(team_side(team) * 960., JUMP_IMAGE_HEIGHT),
+                to: Vec2::new(team_side(target) * 480., JUMP_IMAGE_HEIGHT),
+            });
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use crate::plugins::character_selection::PlayerSettings;
+use crate::plugins::clock::SongClock;
+use crate::plugins::input::{PlayerCommand, PlayerCommandEvent};
+use crate::plugins::sound_player::HitResult;
+use crate::plugins::stats::{Judgement, JudgementEvent};
+use crate::AppState;
+use bevy::{log, prelude::*};
+use bevy_tweening::{lens::SpriteColorLens, Animator, EaseFunction, Tween};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Gauge filled by one Perfect; a full gauge is 1.
+const PERFECT_CHARGE: f32 = 0.04;
+const GOOD_CHARGE: f32 = 0.02;
+const EX_DURATION: Duration = Duration::from_secs(8);
+/// Score multiplier while super mode is on.
+const EX_MULTIPLIER: usize = 2;
+const CUT_IN_DURATION: Duration = Duration::from_millis(1200);
+
+/// EX gauges that fill on good hits and, once full, set off a timed super mode.
+#[derive(Debug)]
+pub struct ExPlugin;
+
+impl Plugin for ExPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ExGauges>()
+            .add_systems(OnEnter(AppState::InGame), reset_ex)
+            .add_systems(
+                Update,
+                (ex_charge_system, ex_activate_system)
+                    .chain()
+                    .run_if(in_state(AppState::InGame)),
+            )
+            .add_systems(OnExit(AppState::InGame), cleanup_cut_ins);
+    }
+}
+
+#[derive(Debug, Default, Resource)]
+pub struct ExGauges(pub(crate) Vec<ExGauge>);
+
+impl ExGauges {
+    pub fn get(&self, team: i32) -> &ExGauge {
+        &self.0[(team - 1) as usize]
+    }
+
+    fn get_mut(&mut self, team: i32) -> Option<&mut ExGauge> {
+        self.0.get_mut((team - 1) as usize)
+    }
+
+    /// Score multiplier of `team` at song position `now`.
+    pub fn multiplier(&self, team: i32, now: Duration) -> usize {
+        match self.0.get((team - 1) as usize) {
+            Some(gauge) if gauge.is_active(now) => EX_MULTIPLIER,
+            _ => 1,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ExGauge {
+    /// From 0 to 1, when it can be set off.
+    pub charge: f32,
+    /// Song position super mode ends at, once it has been set off.
+    pub active_until: Option<Duration>,
+}
+
+impl ExGauge {
+    pub fn is_active(&self, now: Duration) -> bool {
+        self.active_until.is_some_and(|until| now < until)
+    }
+
+    /// How much of super mode is left, from 1 down to 0.
+    pub fn remaining(&self, now: Duration) -> f32 {
+        self.active_until.map_or(0., |until| {
+            until.saturating_sub(now).as_secs_f32() / EX_DURATION.as_secs_f32()
+        })
+    }
+}
+
+/// The character art flashed over the screen when super mode starts.
+#[derive(Debug, Component)]
+struct ExCutIn;
+
+fn reset_ex(mut gauges: ResMut<ExGauges>, settings: Res<PlayerSettings>) {
+    gauges.0 = vec![ExGauge::default(); settings.teams().count()];
+}
+
+fn ex_charge_system(
+    mut judgement_evt: EventReader<JudgementEvent>,
+    mut gauges: ResMut<ExGauges>,
+    clock: Res<SongClock>,
+) {
+    for e in judgement_evt.read() {
+        let Some(gauge) = gauges.get_mut(e.team) else {
+            continue;
+        };
+        // the gauge is spent while super mode is on
+        if gauge.is_active(clock.now()) {
+            continue;
+        }
+
+        let charge = match e.judgement {
+            Judgement::Hit(HitResult::Perfect) => PERFECT_CHARGE,
+            Judgement::Hit(HitResult::Good) => GOOD_CHARGE,
+            _ => continue,
+        };
+        gauge.charge = (gauge.charge + charge).min(1.);
+    }
+}
+
+fn ex_activate_system(
+    mut commands: Commands,
+    mut player_command_evt: EventReader<PlayerCommandEvent>,
+    mut gauges: ResMut<ExGauges>,
+    clock: Res<SongClock>,
+    asset_server: Res<AssetServer>,
+) {
+    for e in player_command_evt.read() {
+        if !e.pressed || !matches!(e.cmd, PlayerCommand::Ex) {
+            continue;
+        }
+        let Some(gauge) = gauges.get_mut(e.team) else {
+            continue;
+        };
+        let now = clock.now();
+        if gauge.charge < 1. || gauge.is_active(now) {
+            continue;
+        }
+
+        log::info!(team = e.team, "EX super mode");
+        gauge.charge = 0.;
+        gauge.active_until = Some(now + EX_DURATION);
+
+        let fade = Tween::new(
+            EaseFunction::QuadraticIn,
+            CUT_IN_DURATION,
+            SpriteColorLens {
+                start: Color::rgba(1., 1., 1., 0.9),
+                end: Color::rgba(1., 1., 1., 0.),
+            },
+        )
+        // despawned by `JumpImagePlugin` once it has faded out
+        .with_completed_event(0);
+        commands.spawn((
+            SpriteBundle {
+                texture: asset_server.load("images/chara1/EX.png"),
+                transform: Transform::from_xyz(0., 0., 60.),
+                ..default()
+            },
+            Animator::new(fade),
+            ExCutIn,
+        ));
+    }
+}
+
+fn cleanup_cut_ins(mut commands: Commands, query: Query<Entity, With<ExCutIn>>) {
+    for ent in &query {
+        commands.entity(ent).despawn();
+    }
+}
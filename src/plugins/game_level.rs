@@ -1,21 +1,62 @@
 use bevy::prelude::*;
 use bevy::sprite::Anchor;
 
+use crate::config::LifeConfig;
+use crate::plugins::character_selection::PlayerSettings;
+use crate::plugins::clock::SongClock;
+use crate::plugins::ex::ExGauges;
+use crate::plugins::life::LifeGauges;
+use crate::plugins::{team_row, team_side};
 use crate::AppState;
 
 pub struct GameLevelUiPlugin;
 
 const BOTTOM_PANEL_HEIGHT: f32 = 200.;
+/// Life gauges run along the top of the bottom panel, from the screen edge
+/// towards the middle.
+const LIFE_BAR_Y: f32 = -540. + BOTTOM_PANEL_HEIGHT - 20.;
+const LIFE_BAR_EDGE_X: f32 = 650.;
+/// Gauges of later teams sit above their lane rows, as far apart as the rows.
+const GAUGE_ROW_SPACING: f32 = 160.;
+const LIFE_BAR_SIZE: Vec2 = Vec2::new(600., 16.);
+const LIFE_COLOR: Color = Color::rgb(0.3, 0.9, 0.4);
+const LOW_LIFE_COLOR: Color = Color::rgb(0.9, 0.2, 0.2);
+const FAILED_COLOR: Color = Color::rgb(0.4, 0.4, 0.4);
+/// EX gauges sit just under the life gauges.
+const EX_BAR_Y: f32 = LIFE_BAR_Y - 18.;
+const EX_BAR_SIZE: Vec2 = Vec2::new(600., 8.);
+/// The part of the `UI_EX.png` HUD mockup each EX gauge is framed with: its
+/// bottom bar, between the P1 keys and the timer.
+const EX_FRAME_RECT: Rect = Rect {
+    min: Vec2::new(560., 945.),
+    max: Vec2::new(860., 1080.),
+};
+/// How far the frame reaches past the gauge on every side.
+const EX_FRAME_PADDING: f32 = 3.;
+const EX_COLOR: Color = Color::rgb(0.3, 0.7, 1.);
+const EX_FULL_COLOR: Color = Color::rgb(1., 0.85, 0.2);
+const EX_ACTIVE_COLOR: Color = Color::rgb(1., 0.4, 0.9);
 
 #[derive(Debug, Component)]
 struct GameUi;
 
-#[derive(Bundle)]
-struct PlayerInfoPanelBundle {
-    sprite: SpriteBundle,
+/// The filled part of a team's life gauge.
+#[derive(Debug, Component)]
+struct LifeBar {
+    team: i32,
+}
+
+/// The filled part of a team's EX gauge.
+#[derive(Debug, Component)]
+struct ExBar {
+    team: i32,
 }
 
-fn add_game_level_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
+fn add_game_level_ui(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    settings: Res<PlayerSettings>,
+) {
     let background = asset_server.load("images/background.png");
     commands.spawn((
         SpriteBundle {
@@ -48,6 +89,119 @@ fn add_game_level_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
         },
         GameUi,
     ));
+
+    // life and EX gauges
+    let ex_frame_img = asset_server.load("images/ui/game/UI_EX.png");
+    for team in settings.teams() {
+        let (x, anchor) = if team_side(team) < 0. {
+            (-LIFE_BAR_EDGE_X, Anchor::CenterLeft)
+        } else {
+            (LIFE_BAR_EDGE_X, Anchor::CenterRight)
+        };
+        let row_y = team_row(team) as f32 * GAUGE_ROW_SPACING;
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba(1., 1., 1., 0.2),
+                    anchor,
+                    custom_size: Some(LIFE_BAR_SIZE),
+                    ..default()
+                },
+                transform: Transform::from_xyz(x, LIFE_BAR_Y + row_y, 1.),
+                ..default()
+            },
+            GameUi,
+        ));
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: LIFE_COLOR,
+                    anchor,
+                    custom_size: Some(LIFE_BAR_SIZE),
+                    ..default()
+                },
+                transform: Transform::from_xyz(x, LIFE_BAR_Y + row_y, 2.),
+                ..default()
+            },
+            LifeBar { team },
+            GameUi,
+        ));
+
+        commands.spawn((
+            SpriteBundle {
+                texture: ex_frame_img.clone(),
+                sprite: Sprite {
+                    anchor,
+                    custom_size: Some(EX_BAR_SIZE + 2. * EX_FRAME_PADDING),
+                    rect: Some(EX_FRAME_RECT),
+                    ..default()
+                },
+                transform: Transform::from_xyz(
+                    x + team_side(team) * EX_FRAME_PADDING,
+                    EX_BAR_Y + row_y,
+                    1.,
+                ),
+                ..default()
+            },
+            GameUi,
+        ));
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: EX_COLOR,
+                    anchor,
+                    custom_size: Some(EX_BAR_SIZE),
+                    ..default()
+                },
+                transform: Transform::from_xyz(x, EX_BAR_Y + row_y, 2.),
+                ..default()
+            },
+            ExBar { team },
+            GameUi,
+        ));
+    }
+}
+
+fn life_bar_update_system(
+    gauges: Res<LifeGauges>,
+    config: Res<LifeConfig>,
+    mut query: Query<(&LifeBar, &mut Sprite)>,
+) {
+    for (bar, mut sprite) in &mut query {
+        let gauge = gauges.get(bar.team);
+        let fill = gauge.life / config.max;
+        sprite.custom_size = Some(Vec2::new(LIFE_BAR_SIZE.x * fill, LIFE_BAR_SIZE.y));
+        sprite.color = if gauge.failed {
+            FAILED_COLOR
+        } else if fill < 0.25 {
+            LOW_LIFE_COLOR
+        } else {
+            LIFE_COLOR
+        };
+    }
+}
+
+/// Fill the EX gauges while charging, then drain them through super mode.
+fn ex_bar_update_system(
+    gauges: Res<ExGauges>,
+    clock: Res<SongClock>,
+    mut query: Query<(&ExBar, &mut Sprite)>,
+) {
+    let now = clock.now();
+    for (bar, mut sprite) in &mut query {
+        let gauge = gauges.get(bar.team);
+        let (fill, color) = if gauge.is_active(now) {
+            (gauge.remaining(now), EX_ACTIVE_COLOR)
+        } else if gauge.charge >= 1. {
+            // blink to say it is ready
+            let on = ((now.as_secs_f32() * 4.) as u32) % 2 == 0;
+            (1., if on { EX_FULL_COLOR } else { Color::WHITE })
+        } else {
+            (gauge.charge, EX_COLOR)
+        };
+        sprite.custom_size = Some(Vec2::new(EX_BAR_SIZE.x * fill, EX_BAR_SIZE.y));
+        sprite.color = color;
+    }
 }
 
 fn remove_game_level_ui<T: Component>(mut commands: Commands, query: Query<Entity, With<T>>) {
@@ -58,7 +212,15 @@ fn remove_game_level_ui<T: Component>(mut commands: Commands, query: Query<Entit
 
 impl Plugin for GameLevelUiPlugin {
     fn build(&self, app: &mut App) {
+        // spectators see the same scene, fed from the broadcast
         app.add_systems(OnEnter(AppState::InGame), add_game_level_ui)
-            .add_systems(OnExit(AppState::InGame), remove_game_level_ui::<GameUi>);
+            .add_systems(OnEnter(AppState::Spectating), add_game_level_ui)
+            .add_systems(
+                Update,
+                (life_bar_update_system, ex_bar_update_system)
+                    .run_if(in_state(AppState::InGame).or_else(in_state(AppState::Spectating))),
+            )
+            .add_systems(OnExit(AppState::InGame), remove_game_level_ui::<GameUi>)
+            .add_systems(OnExit(AppState::Spectating), remove_game_level_ui::<GameUi>);
     }
 }
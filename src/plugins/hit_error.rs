@@ -0,0 +1,161 @@
+use crate::plugins::character_selection::PlayerSettings;
+use crate::plugins::popup::judgement_color;
+use crate::plugins::sound_player::{hit_ring, HitResult};
+use crate::plugins::stats::{Judgement, JudgementEvent};
+use crate::AppState;
+use bevy::prelude::*;
+
+/// Hits plotted at a time; older ones fade out and are dropped.
+const MAX_TICKS: usize = 20;
+/// Centre of the meter, relative to the hit ring.
+const METER_OFFSET: Vec2 = Vec2::new(0., -65.);
+/// Half the meter's width, which spans the team's whole miss window.
+const METER_HALF_WIDTH: f32 = 150.;
+const METER_HEIGHT: f32 = 8.;
+const TICK_SIZE: Vec2 = Vec2::new(3., 24.);
+
+/// Plot how early or late each player's recent hits were, under their ring.
+#[derive(Debug)]
+pub struct HitErrorMeterPlugin;
+
+impl Plugin for HitErrorMeterPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(AppState::InGame), setup_meters)
+            .add_systems(
+                Update,
+                (add_tick_system, fade_tick_system)
+                    .chain()
+                    .run_if(in_state(AppState::InGame)),
+            )
+            .add_systems(OnExit(AppState::InGame), cleanup_meters);
+    }
+}
+
+#[derive(Debug, Component)]
+struct HitErrorMeter {
+    team: i32,
+    /// Pixels per millisecond of offset.
+    scale: f32,
+    /// Ticks added so far, used to order them by age.
+    ticks: usize,
+}
+
+#[derive(Debug, Component)]
+struct HitErrorTick {
+    /// `HitErrorMeter::ticks` when this one was added.
+    index: usize,
+    color: Color,
+}
+
+fn setup_meters(mut commands: Commands, settings: Res<PlayerSettings>) {
+    for team in settings.teams() {
+        if !settings.get(team).hit_error_meter {
+            continue;
+        }
+
+        let windows = settings.get(team).difficulty.windows();
+        let scale = METER_HALF_WIDTH / windows.miss as f32;
+        // one band per judgement window, the narrowest drawn on top
+        let bands = [
+            (windows.miss, HitResult::Miss),
+            (windows.ok, HitResult::Ok),
+            (windows.good, HitResult::Good),
+            (windows.perfect, HitResult::Perfect),
+        ];
+        let center = hit_ring(team) + METER_OFFSET;
+        commands
+            .spawn((
+                SpatialBundle::from_transform(Transform::from_translation(center.extend(20.))),
+                HitErrorMeter {
+                    team,
+                    scale,
+                    ticks: 0,
+                },
+            ))
+            .with_children(|parent| {
+                for (z, (window, result)) in bands.into_iter().enumerate() {
+                    parent.spawn(SpriteBundle {
+                        sprite: Sprite {
+                            color: judgement_color(Judgement::Hit(result)).with_a(0.5),
+                            custom_size: Some(Vec2::new(window as f32 * scale * 2., METER_HEIGHT)),
+                            ..default()
+                        },
+                        transform: Transform::from_xyz(0., 0., z as f32 * 0.1),
+                        ..default()
+                    });
+                }
+                // the exact hit point
+                parent.spawn(SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::WHITE,
+                        custom_size: Some(Vec2::new(1., TICK_SIZE.y)),
+                        ..default()
+                    },
+                    transform: Transform::from_xyz(0., 0., 0.5),
+                    ..default()
+                });
+            });
+    }
+}
+
+fn add_tick_system(
+    mut commands: Commands,
+    mut judgement_evt: EventReader<JudgementEvent>,
+    mut meter_query: Query<(Entity, &mut HitErrorMeter)>,
+) {
+    for e in judgement_evt.read() {
+        let Some(offset) = e.offset else {
+            continue;
+        };
+        let Some((ent, mut meter)) = meter_query.iter_mut().find(|(_, m)| m.team == e.team) else {
+            continue;
+        };
+
+        let x = (offset * meter.scale).clamp(-METER_HALF_WIDTH, METER_HALF_WIDTH);
+        let color = judgement_color(e.judgement);
+        let index = meter.ticks;
+        meter.ticks += 1;
+        commands.entity(ent).with_children(|parent| {
+            parent.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color,
+                        custom_size: Some(TICK_SIZE),
+                        ..default()
+                    },
+                    transform: Transform::from_xyz(x, 0., 1.),
+                    ..default()
+                },
+                HitErrorTick { index, color },
+            ));
+        });
+    }
+}
+
+/// Fade ticks with age and drop the ones past `MAX_TICKS`.
+fn fade_tick_system(
+    mut commands: Commands,
+    meter_query: Query<(&HitErrorMeter, &Children)>,
+    mut tick_query: Query<(Entity, &HitErrorTick, &mut Sprite)>,
+) {
+    for (meter, children) in &meter_query {
+        for child in children {
+            let Ok((ent, tick, mut sprite)) = tick_query.get_mut(*child) else {
+                continue;
+            };
+            let age = meter.ticks - tick.index - 1;
+            if age >= MAX_TICKS {
+                commands.entity(ent).despawn_recursive();
+                continue;
+            }
+            let alpha = 1. - age as f32 / MAX_TICKS as f32;
+            sprite.color = tick.color.with_a(alpha);
+        }
+    }
+}
+
+fn cleanup_meters(mut commands: Commands, query: Query<Entity, With<HitErrorMeter>>) {
+    for ent in &query {
+        commands.entity(ent).despawn_recursive();
+    }
+}
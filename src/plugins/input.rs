@@ -1,33 +1,73 @@
+use crate::plugins::clock::SongClock;
+use crate::plugins::net::NetSession;
+#[cfg(all(target_os = "windows", feature = "ringcon"))]
 use crate::plugins::ringcon::RingConEvent;
 use bevy::{
     app::AppExit,
-    input::{keyboard::KeyboardInput, ButtonState},
+    input::{keyboard::KeyboardInput, ButtonState, InputSystem},
     log,
     prelude::*,
+    utils::HashSet,
 };
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long before the previous frame read its inputs a key hook stamp is
+/// kept, for keys that came in while that frame was starting.
+const STAMP_SLACK: Duration = Duration::from_millis(50);
 
 #[derive(Debug)]
 pub struct GameInputPlugin;
 
 impl Plugin for GameInputPlugin {
     fn build(&self, app: &mut App) {
-        app.add_event::<PlayerCommandEvent>().add_systems(
-            Update,
-            (
-                check_keyboard_input,
-                // debug_player_command,
-                #[cfg(all(target_os = "windows", feature = "ringcon"))]
-                check_ringcon_input,
-            ),
-        );
+        // read inputs first thing in the frame; keys are stamped with the time the
+        // key hook saw them, so a slow frame doesn't make them late
+        app.add_event::<PlayerCommandEvent>()
+            .add_systems(Startup, start_key_hook)
+            .add_systems(
+                PreUpdate,
+                (
+                    check_keyboard_input,
+                    #[cfg(all(target_os = "windows", feature = "ringcon"))]
+                    check_ringcon_input,
+                )
+                    .after(InputSystem),
+            );
     }
 }
 
-#[derive(Debug)]
+/// `Hit1`, `Hit2`, `Hit3` and `Ex` keys of each team.
+pub(crate) const TEAM_KEYS: [[KeyCode; 4]; 4] = [
+    [KeyCode::A, KeyCode::W, KeyCode::D, KeyCode::S],
+    [KeyCode::G, KeyCode::Y, KeyCode::J, KeyCode::H],
+    [KeyCode::Z, KeyCode::X, KeyCode::C, KeyCode::V],
+    [
+        KeyCode::Numpad1,
+        KeyCode::Numpad2,
+        KeyCode::Numpad3,
+        KeyCode::Numpad0,
+    ],
+];
+/// Most teams a match can have, one per key set.
+pub const MAX_TEAMS: usize = TEAM_KEYS.len();
+
+/// Name of `team`'s key for lane `key`, as printed on its notes and hints.
+pub(crate) fn key_label(team: i32, key: i32) -> String {
+    let key_code = TEAM_KEYS[(team - 1) as usize][(key - 1) as usize];
+    format!("{key_code:?}").replace("Numpad", "")
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum PlayerCommand {
     Hit1,
     Hit2,
     Hit3,
+    /// Set off the EX super mode.
+    Ex,
     Exit,
 }
 
@@ -35,93 +75,264 @@ pub enum PlayerCommand {
 pub struct PlayerCommandEvent {
     pub team: i32,
     pub cmd: PlayerCommand,
+    /// `false` when the key is let go.
+    pub pressed: bool,
+    /// The input is never let go, like a RingCon move; hold notes hit with it
+    /// are judged on the press alone.
+    pub tap: bool,
+    /// Song position the input was made at, or at the start of the frame it was
+    /// read in when the key hook didn't catch it.
+    pub time: Duration,
+}
+
+/// Key events caught by an OS keyboard hook on its own thread, stamped as they
+/// happen instead of when the next frame gets to them.
+#[derive(Resource)]
+struct KeyHook {
+    rx: Mutex<Receiver<KeyStamp>>,
+    /// Stamps not yet matched with a key event of the window.
+    pending: Vec<KeyStamp>,
+    /// When the last frame read its inputs.
+    last_read: Option<Instant>,
+}
+
+#[derive(Debug)]
+struct KeyStamp {
+    key: rdev::Key,
+    pressed: bool,
+    at: Instant,
+}
+
+fn start_key_hook(mut commands: Commands) {
+    let (tx, rx) = mpsc::channel();
+    let hook = thread::Builder::new()
+        .name("key hook".to_string())
+        .spawn(move || {
+            let listened = rdev::listen(move |event| {
+                let (key, pressed) = match event.event_type {
+                    rdev::EventType::KeyPress(key) => (key, true),
+                    rdev::EventType::KeyRelease(key) => (key, false),
+                    _ => return,
+                };
+                let at = Instant::now();
+                // the game has quit once nobody receives
+                let _ = tx.send(KeyStamp { key, pressed, at });
+            });
+            if let Err(e) = listened {
+                log::warn!("no keyboard hook, keys are timed by frame: {e:?}");
+            }
+        });
+    match hook {
+        Ok(_) => commands.insert_resource(KeyHook::new(rx)),
+        Err(e) => log::warn!("no keyboard hook, keys are timed by frame: {e}"),
+    }
+}
+
+/// The hook's name for the keys in `TEAM_KEYS`.
+fn hook_key(key_code: KeyCode) -> Option<rdev::Key> {
+    use rdev::Key;
+    let key = match key_code {
+        KeyCode::A => Key::KeyA,
+        KeyCode::W => Key::KeyW,
+        KeyCode::D => Key::KeyD,
+        KeyCode::S => Key::KeyS,
+        KeyCode::G => Key::KeyG,
+        KeyCode::Y => Key::KeyY,
+        KeyCode::J => Key::KeyJ,
+        KeyCode::H => Key::KeyH,
+        KeyCode::Z => Key::KeyZ,
+        KeyCode::X => Key::KeyX,
+        KeyCode::C => Key::KeyC,
+        KeyCode::V => Key::KeyV,
+        KeyCode::Numpad0 => Key::Kp0,
+        KeyCode::Numpad1 => Key::Kp1,
+        KeyCode::Numpad2 => Key::Kp2,
+        KeyCode::Numpad3 => Key::Kp3,
+        _ => return None,
+    };
+    Some(key)
+}
+
+impl KeyHook {
+    fn new(rx: Receiver<KeyStamp>) -> Self {
+        Self {
+            rx: Mutex::new(rx),
+            pending: Vec::new(),
+            last_read: None,
+        }
+    }
+
+    /// Pick up the stamps the hook caught since the last frame.
+    fn read(&mut self, now: Instant) {
+        self.pending.extend(self.rx.get_mut().unwrap().try_iter());
+        // keys pressed while the window is unfocused never reach us
+        if let Some(last_read) = self.last_read.replace(now) {
+            self.pending.retain(|s| s.at + STAMP_SLACK >= last_read);
+        }
+    }
+
+    /// Take the first stamp of `key_code` going down (or up), dropping the
+    /// older ones of that key along with it: OS key repeats, or stamps that
+    /// came in too late for their frame.
+    fn take(&mut self, key_code: KeyCode, pressed: bool) -> Option<Instant> {
+        let key = hook_key(key_code)?;
+        let at = self
+            .pending
+            .iter()
+            .find(|s| s.key == key && s.pressed == pressed)?
+            .at;
+        self.pending.retain(|s| s.key != key || s.at > at);
+        Some(at)
+    }
 }
 
 fn check_keyboard_input(
     mut kbd_evt: EventReader<KeyboardInput>,
     mut exit_evt_w: EventWriter<AppExit>,
     mut player_cmd_evt_w: EventWriter<PlayerCommandEvent>,
+    clock: Res<SongClock>,
+    net: Option<Res<NetSession>>,
+    mut hook: Option<ResMut<KeyHook>>,
+    mut held: Local<HashSet<KeyCode>>,
 ) {
+    let now = Instant::now();
+    let frame_time = clock.position_at(now);
+    if let Some(hook) = &mut hook {
+        hook.read(now);
+    }
+    // over the network, this machine's player uses P1's keys for their team
+    let team_keys: Vec<(i32, [KeyCode; 4])> = match &net {
+        Some(net) => vec![(net.local_team(), TEAM_KEYS[0])],
+        None => (1..).zip(TEAM_KEYS).collect(),
+    };
     for e in kbd_evt.read() {
-        if e.state != ButtonState::Pressed {
+        let Some(key_code) = e.key_code else {
+            continue;
+        };
+        let pressed = e.state == ButtonState::Pressed;
+        // drop the OS key repeat while a key is held down
+        if pressed && !held.insert(key_code) {
             continue;
         }
+        if !pressed {
+            held.remove(&key_code);
+        }
+
+        let time = hook
+            .as_mut()
+            .and_then(|hook| hook.take(key_code, pressed))
+            .map_or(frame_time, |at| clock.position_at(at));
 
-        match e.key_code {
-            Some(KeyCode::Escape) => {
+        if key_code == KeyCode::Escape {
+            if pressed {
                 exit_evt_w.send(AppExit);
             }
-            Some(KeyCode::A) => {
-                player_cmd_evt_w.send(PlayerCommandEvent {
-                    team: 1,
-                    cmd: PlayerCommand::Hit1,
-                });
-            }
-            Some(KeyCode::W) => {
-                player_cmd_evt_w.send(PlayerCommandEvent {
-                    team: 1,
-                    cmd: PlayerCommand::Hit2,
-                });
-            }
-            Some(KeyCode::D) => {
-                player_cmd_evt_w.send(PlayerCommandEvent {
-                    team: 1,
-                    cmd: PlayerCommand::Hit3,
-                });
-            }
-            Some(KeyCode::G) => {
-                player_cmd_evt_w.send(PlayerCommandEvent {
-                    team: 2,
-                    cmd: PlayerCommand::Hit1,
-                });
-            }
-            Some(KeyCode::Y) => {
-                player_cmd_evt_w.send(PlayerCommandEvent {
-                    team: 2,
-                    cmd: PlayerCommand::Hit2,
-                });
-            }
-            Some(KeyCode::J) => {
-                player_cmd_evt_w.send(PlayerCommandEvent {
-                    team: 2,
-                    cmd: PlayerCommand::Hit3,
-                });
-            }
-            _ => {}
+            continue;
+        }
+        for &(team, keys) in &team_keys {
+            let Some(index) = keys.iter().position(|k| *k == key_code) else {
+                continue;
+            };
+            let cmd = match index {
+                0 => PlayerCommand::Hit1,
+                1 => PlayerCommand::Hit2,
+                2 => PlayerCommand::Hit3,
+                _ => PlayerCommand::Ex,
+            };
+            player_cmd_evt_w.send(PlayerCommandEvent {
+                team,
+                cmd,
+                pressed,
+                tap: false,
+                time,
+            });
         }
     }
 }
 
+#[cfg(all(target_os = "windows", feature = "ringcon"))]
 fn check_ringcon_input(
     mut ringcon_evt: EventReader<RingConEvent>,
     mut player_cmd_evt_w: EventWriter<PlayerCommandEvent>,
+    clock: Res<SongClock>,
 ) {
+    let time = clock.now();
     for e in ringcon_evt.read() {
         match e {
             RingConEvent::Push => {
                 player_cmd_evt_w.send(PlayerCommandEvent {
                     team: 1,
                     cmd: PlayerCommand::Hit1,
+                    pressed: true,
+                    tap: true,
+                    time,
                 });
             }
             RingConEvent::Pull => {
                 player_cmd_evt_w.send(PlayerCommandEvent {
                     team: 1,
                     cmd: PlayerCommand::Hit2,
+                    pressed: true,
+                    tap: true,
+                    time,
                 });
             }
             RingConEvent::Squat => {
                 player_cmd_evt_w.send(PlayerCommandEvent {
                     team: 1,
                     cmd: PlayerCommand::Hit3,
+                    pressed: true,
+                    tap: true,
+                    time,
                 });
             }
         }
     }
 }
 
-fn debug_player_command(mut evt: EventReader<PlayerCommandEvent>) {
-    for e in evt.read() {
-        log::trace!("{e:?}");
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hook_with(stamps: &[(rdev::Key, bool, Instant)]) -> KeyHook {
+        let (tx, rx) = mpsc::channel();
+        for &(key, pressed, at) in stamps {
+            tx.send(KeyStamp { key, pressed, at }).unwrap();
+        }
+        KeyHook::new(rx)
+    }
+
+    #[test]
+    fn keys_take_the_time_the_hook_saw_them() {
+        let start = Instant::now();
+        let ms = |n| start + Duration::from_millis(n);
+        // A held through two OS key repeats, then pressed again
+        let mut hook = hook_with(&[
+            (rdev::Key::KeyA, true, ms(0)),
+            (rdev::Key::KeyW, true, ms(5)),
+            (rdev::Key::KeyA, true, ms(30)),
+            (rdev::Key::KeyA, true, ms(60)),
+            (rdev::Key::KeyA, false, ms(70)),
+            (rdev::Key::KeyA, true, ms(90)),
+        ]);
+        hook.read(ms(100));
+        assert_eq!(hook.take(KeyCode::A, true), Some(ms(0)));
+        assert_eq!(hook.take(KeyCode::A, false), Some(ms(70)));
+        assert_eq!(hook.take(KeyCode::A, true), Some(ms(90)));
+        assert_eq!(hook.take(KeyCode::A, true), None);
+        assert_eq!(hook.take(KeyCode::W, true), Some(ms(5)));
+        assert_eq!(hook.take(KeyCode::Escape, true), None);
+    }
+
+    #[test]
+    fn stamps_the_window_never_got_are_dropped() {
+        let start = Instant::now();
+        let ms = |n| start + Duration::from_millis(n);
+        let mut hook = hook_with(&[(rdev::Key::KeyA, true, ms(0))]);
+        // the window was unfocused for the frames read at 10 and 100
+        for now in [10, 100, 110] {
+            hook.read(ms(now));
+        }
+        assert_eq!(hook.take(KeyCode::A, true), None);
     }
 }
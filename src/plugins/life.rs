@@ -0,0 +1,96 @@
+use crate::config::{load_config, FailBehaviour, LifeConfig};
+use crate::plugins::character_selection::PlayerSettings;
+use crate::plugins::sound_player::HitResult;
+use crate::plugins::stats::{Judgement, JudgementEvent};
+use crate::AppState;
+use bevy::{log, prelude::*};
+use serde::{Deserialize, Serialize};
+
+/// Per-team life gauges, drained by misses and refilled by hits.
+#[derive(Debug)]
+pub struct LifePlugin;
+
+impl Plugin for LifePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(load_config::<LifeConfig>("life.ron"))
+            .init_resource::<LifeGauges>()
+            .add_systems(OnEnter(AppState::InGame), reset_life)
+            .add_systems(Update, life_system.run_if(in_state(AppState::InGame)));
+    }
+}
+
+#[derive(Debug, Default, Resource)]
+pub struct LifeGauges(pub(crate) Vec<LifeGauge>);
+
+impl LifeGauges {
+    pub fn get(&self, team: i32) -> &LifeGauge {
+        &self.0[(team - 1) as usize]
+    }
+
+    pub(crate) fn get_mut(&mut self, team: i32) -> Option<&mut LifeGauge> {
+        self.0.get_mut((team - 1) as usize)
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct LifeGauge {
+    pub life: f32,
+    /// Set once `life` has run out; it stays set for the rest of the match.
+    pub failed: bool,
+}
+
+fn reset_life(
+    mut gauges: ResMut<LifeGauges>,
+    settings: Res<PlayerSettings>,
+    config: Res<LifeConfig>,
+) {
+    let gauge = LifeGauge {
+        life: config.start.min(config.max),
+        failed: false,
+    };
+    gauges.0 = vec![gauge; settings.teams().count()];
+}
+
+fn life_system(
+    mut judgement_evt: EventReader<JudgementEvent>,
+    mut gauges: ResMut<LifeGauges>,
+    config: Res<LifeConfig>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    for e in judgement_evt.read() {
+        let Some(gauge) = gauges.get_mut(e.team) else {
+            continue;
+        };
+
+        let change = match e.judgement {
+            Judgement::Hit(HitResult::Perfect) => config.perfect_gain,
+            Judgement::Hit(HitResult::Good) => config.good_gain,
+            Judgement::Hit(HitResult::Ok) => config.ok_gain,
+            Judgement::Hit(HitResult::Miss) => -config.miss_damage,
+            Judgement::WrongKey => -config.wrong_key_damage,
+        };
+        change_life(gauge, e.team, change, &config, &mut next_state);
+    }
+}
+
+/// Add `change` to a team's life, failing the team once it runs out.
+pub(crate) fn change_life(
+    gauge: &mut LifeGauge,
+    team: i32,
+    change: f32,
+    config: &LifeConfig,
+    next_state: &mut NextState<AppState>,
+) {
+    if gauge.failed {
+        return;
+    }
+    gauge.life = (gauge.life + change).clamp(0., config.max);
+
+    if gauge.life <= 0. {
+        log::info!(team, "team failed");
+        gauge.failed = true;
+        if config.fail == FailBehaviour::EndMatch {
+            next_state.set(AppState::Results);
+        }
+    }
+}
@@ -1,12 +1,29 @@
 pub mod art;
+pub mod calibration;
 pub mod character_selection;
+pub mod chart;
+pub mod clock;
+pub mod combat;
+pub mod combo_tier;
+pub mod editor;
+pub mod effects;
+pub mod ex;
 pub mod game_level;
+pub mod hit_error;
 pub mod input;
+pub mod life;
+pub mod net;
+pub mod popup;
+pub mod results;
+#[cfg(all(target_os = "windows", feature = "ringcon"))]
 pub mod ringcon;
 pub mod score;
 pub mod seventeen;
 pub mod sound_player;
+pub mod spectator;
 pub mod start_menu;
+pub mod stats;
+pub mod tug;
 
 use std::time::Duration;
 
@@ -24,14 +41,8 @@ pub struct JumpImage {
     pub(crate) to: Vec2,
 }
 
-/// play one-shot SFV
-pub struct PlaySfxEvent;
-
 pub struct JumpImagePlugin;
 
-#[derive(Debug, Event)]
-pub struct RemoveEntity(Entity);
-
 fn spawn_jump_image(
     mut commands: Commands,
     query: Query<(Entity, &JumpImage)>,
@@ -86,7 +97,11 @@ fn spawn_jump_image(
 
 fn despawn_jump_image(mut evt: EventReader<TweenCompleted>, mut commands: Commands) {
     for e in evt.read() {
-        commands.entity(e.entity).despawn();
+        // popups and cut-ins can be despawned early, by the next one or on
+        // leaving the match, with their tween's event still pending
+        if let Some(mut ent) = commands.get_entity(e.entity) {
+            ent.despawn();
+        }
     }
 }
 
@@ -98,3 +113,18 @@ impl Plugin for JumpImagePlugin {
 
 #[derive(Debug, Component, PartialEq, Eq)]
 pub struct Team(pub i32);
+
+/// Which half of the screen `team` plays on: -1 for odd teams on the left,
+/// 1 for even teams on the right.
+pub(crate) fn team_side(team: i32) -> f32 {
+    if team % 2 == 1 {
+        -1.
+    } else {
+        1.
+    }
+}
+
+/// Row of `team` on its half, 0 for teams 1 and 2 and counting up from there.
+pub(crate) fn team_row(team: i32) -> i32 {
+    (team - 1) / 2
+}
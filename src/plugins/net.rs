@@ -0,0 +1,825 @@
+use crate::config::{Difficulty, GameModes};
+use crate::plugins::calibration::Latency;
+use crate::plugins::character_selection::{PlayerSetting, PlayerSettings};
+use crate::plugins::clock::SongClock;
+use crate::plugins::effects::MatchSeed;
+use crate::plugins::input::{PlayerCommand, PlayerCommandEvent};
+use crate::plugins::sound_player::sync_song_clock;
+use crate::plugins::stats::{Judgement, JudgementEvent};
+use crate::AppState;
+use bevy::{input::InputSystem, log, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+const DEFAULT_PORT: u16 = 7777;
+/// How long after the host starts a match the song starts on both machines.
+const START_DELAY: Duration = Duration::from_secs(3);
+const PING_INTERVAL: Duration = Duration::from_millis(500);
+/// Most reliable messages sent in one datagram.
+const MAX_BATCH: usize = 32;
+/// How much longer than the one-way delay the peer's notes wait for its
+/// inputs before they count as missed.
+const REMOTE_GRACE: Duration = Duration::from_millis(50);
+/// Silence after which the peer is shown as gone.
+const PEER_TIMEOUT: Duration = Duration::from_secs(5);
+const STATUS_FONT_SIZE: f32 = 24.;
+
+/// Versus play between two machines over UDP, set up from the command line:
+///
+/// - `--host [port]` waits for a peer and plays as P1,
+/// - `--join <address>[:port]` joins a host and plays as P2.
+///
+/// Each side sends its own player's picks for the match; the host starts it on
+/// both machines once both players are ready. During the match each side sends
+/// its player's inputs, stamped with the song position they were judged at, and
+/// the judgements they got; the peer replays the inputs on its copy of the
+/// match and checks that its judgements agree.
+#[derive(Debug)]
+pub struct NetPlugin;
+
+impl Plugin for NetPlugin {
+    fn build(&self, app: &mut App) {
+        let Some(role) = NetRole::from_args(std::env::args().skip(1)) else {
+            return;
+        };
+        let session = match NetSession::bind(role) {
+            Ok(session) => session,
+            Err(e) => {
+                log::error!(?role, "could not open the network socket: {e}");
+                return;
+            }
+        };
+
+        app.insert_resource(session)
+            .add_systems(Startup, setup_net_status)
+            .add_systems(PreUpdate, receive_system.after(InputSystem))
+            .add_systems(OnEnter(AppState::InGame), start_match)
+            .add_systems(OnExit(AppState::InGame), stop_match)
+            .add_systems(
+                Update,
+                start_clock_system
+                    .before(sync_song_clock)
+                    .run_if(in_state(AppState::InGame)),
+            )
+            .add_systems(
+                PostUpdate,
+                (
+                    match_setup_system,
+                    send_command_system,
+                    judgement_exchange_system,
+                    flush_system,
+                    net_status_update_system,
+                )
+                    .chain(),
+            );
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum NetRole {
+    Host { port: u16 },
+    Join { host: SocketAddr },
+}
+
+impl NetRole {
+    fn from_args(mut args: impl Iterator<Item = String>) -> Option<Self> {
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--host" => {
+                    let port = args.next().and_then(|port| port.parse().ok());
+                    return Some(Self::Host {
+                        port: port.unwrap_or(DEFAULT_PORT),
+                    });
+                }
+                "--join" => {
+                    let host = args
+                        .next()
+                        .and_then(|host| parse_address(&host, DEFAULT_PORT));
+                    let Some(host) = host else {
+                        log::error!("--join needs the host's address");
+                        return None;
+                    };
+                    return Some(Self::Join { host });
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+/// `host:port`, or just `host` on `default_port`.
+pub(crate) fn parse_address(text: &str, default_port: u16) -> Option<SocketAddr> {
+    use std::net::ToSocketAddrs;
+
+    let with_port = if text.contains(':') {
+        text.to_string()
+    } else {
+        format!("{text}:{default_port}")
+    };
+    with_port.to_socket_addrs().ok()?.next()
+}
+
+/// One datagram: unacknowledged reliable messages, resent until the peer has
+/// them, plus clock sync that is not.
+#[derive(Debug, Serialize, Deserialize)]
+struct Packet {
+    /// Last reliable message received, all before it included.
+    ack: u64,
+    /// Oldest first.
+    messages: Vec<(u64, NetMessage)>,
+    sync: Vec<ClockSync>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum NetMessage {
+    /// What the host picked for the next match.
+    Setup { seed: u64, mode: usize },
+    /// What the sender's player picked, and whether they are ready to play
+    /// with it.
+    Player { setting: NetSetting, ready: bool },
+    /// The host starts the match with these picks, one per team; the peer
+    /// follows even if its player just stopped being ready.
+    Begin {
+        seed: u64,
+        mode: usize,
+        settings: Vec<NetSetting>,
+    },
+    /// The sender wants to play the match again; the rematch starts once both
+    /// players asked for it.
+    Rematch,
+    /// Song position 0 of the next match, in the sender's `millis`.
+    Start { at: u64 },
+    /// An input of the sender's player; `time` is the song position it is
+    /// judged at, after the sender's latency offset.
+    Command {
+        cmd: PlayerCommand,
+        pressed: bool,
+        tap: bool,
+        time: Duration,
+    },
+    /// A judgement the sender's player got, in order.
+    Judged(Judgement),
+}
+
+/// The picks of a player that change how their match plays.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct NetSetting {
+    difficulty: Difficulty,
+    track: usize,
+    scroll_speed: f32,
+}
+
+impl NetSetting {
+    fn of(setting: &PlayerSetting) -> Self {
+        Self {
+            difficulty: setting.difficulty,
+            track: setting.track,
+            scroll_speed: setting.scroll_speed,
+        }
+    }
+
+    fn apply(&self, setting: &mut PlayerSetting) {
+        setting.difficulty = self.difficulty;
+        setting.track = self.track;
+        setting.scroll_speed = self.scroll_speed;
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+enum ClockSync {
+    /// Sent at the sender's `millis`.
+    Ping(u64),
+    /// Answer to `Ping`, with when it was received.
+    Pong(u64, u64),
+}
+
+#[derive(Debug, Resource)]
+pub struct NetSession {
+    role: NetRole,
+    socket: UdpSocket,
+    peer: Option<SocketAddr>,
+    last_heard: Option<Instant>,
+    /// Zero of `millis`.
+    epoch: Instant,
+    /// Latest round trip time.
+    rtt: Option<Duration>,
+    /// The peer's `millis` minus ours, and the round trip it was measured
+    /// over; the quickest round trip gives the best estimate.
+    offset: Option<(i64, u64)>,
+    last_ping: Option<Instant>,
+    sync: Vec<ClockSync>,
+    next_seq: u64,
+    outbox: VecDeque<(u64, NetMessage)>,
+    /// Last reliable message received.
+    received: u64,
+    /// The host still has to send its setup to a new peer.
+    needs_setup: bool,
+    /// What we last told the peer about our player, see `NetMessage::Player`.
+    shared: Option<(NetSetting, bool)>,
+    /// Whether the peer's player is ready to start.
+    peer_ready: bool,
+    /// Whether we, and the peer, asked for a rematch of the last match.
+    rematch: bool,
+    peer_rematch: bool,
+    /// When song position 0 of the current match is.
+    start_at: Option<Instant>,
+    /// The peer's judgements, and ours of its player, still to be compared.
+    remote_judgements: VecDeque<Judgement>,
+    replayed_judgements: VecDeque<Judgement>,
+    /// Judgements of the peer's player we disagreed with this match.
+    pub desyncs: usize,
+}
+
+impl NetSession {
+    pub fn bind(role: NetRole) -> std::io::Result<Self> {
+        let (local, peer) = match role {
+            NetRole::Host { port } => (SocketAddr::from(([0, 0, 0, 0], port)), None),
+            NetRole::Join { host } => (SocketAddr::from(([0, 0, 0, 0], 0)), Some(host)),
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.set_nonblocking(true)?;
+        log::info!(?role, address = ?socket.local_addr()?, "network session");
+
+        Ok(Self {
+            role,
+            socket,
+            peer,
+            last_heard: None,
+            epoch: Instant::now(),
+            rtt: None,
+            offset: None,
+            last_ping: None,
+            sync: Vec::new(),
+            next_seq: 1,
+            outbox: VecDeque::new(),
+            received: 0,
+            needs_setup: false,
+            shared: None,
+            peer_ready: false,
+            rematch: false,
+            peer_rematch: false,
+            start_at: None,
+            remote_judgements: VecDeque::new(),
+            replayed_judgements: VecDeque::new(),
+            desyncs: 0,
+        })
+    }
+
+    pub fn is_host(&self) -> bool {
+        matches!(self.role, NetRole::Host { .. })
+    }
+
+    /// The team played on this machine.
+    pub fn local_team(&self) -> i32 {
+        if self.is_host() {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// The team played on the other machine.
+    pub fn remote_team(&self) -> i32 {
+        3 - self.local_team()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.last_heard
+            .is_some_and(|heard| heard.elapsed() < PEER_TIMEOUT)
+    }
+
+    /// Whether the song of the current match has started.
+    pub fn started(&self) -> bool {
+        self.start_at.is_some_and(|at| Instant::now() >= at)
+    }
+
+    /// Song position `team`'s notes are checked for misses at, when it is
+    /// `now` here: the peer's inputs get here late, and their notes wait for
+    /// them.
+    pub fn sweep_time(&self, team: i32, now: Duration) -> Duration {
+        if team != self.remote_team() {
+            return now;
+        }
+        let delay = self.rtt.unwrap_or_default() / 2 + REMOTE_GRACE;
+        now.saturating_sub(delay)
+    }
+
+    pub fn peer_ready(&self) -> bool {
+        self.peer_ready
+    }
+
+    /// Tell the peer what this machine's player picked, if it changed.
+    pub(crate) fn share_player(&mut self, setting: &PlayerSetting, ready: bool) {
+        let player = (NetSetting::of(setting), ready);
+        if self.shared != Some(player) {
+            self.shared = Some(player);
+            self.send(NetMessage::Player {
+                setting: player.0,
+                ready,
+            });
+        }
+    }
+
+    /// Start the match on both machines; the host does once both players are
+    /// ready.
+    pub(crate) fn begin(&mut self, seed: u64, mode: usize, settings: &PlayerSettings) {
+        let settings = settings
+            .teams()
+            .map(|team| NetSetting::of(settings.get(team)))
+            .collect();
+        self.send(NetMessage::Begin {
+            seed,
+            mode,
+            settings,
+        });
+    }
+
+    /// Ask the peer for a rematch, returning whether it already asked too.
+    pub(crate) fn request_rematch(&mut self) -> bool {
+        if !self.rematch {
+            self.rematch = true;
+            self.send(NetMessage::Rematch);
+        }
+        self.peer_rematch
+    }
+
+    fn millis(&self, at: Instant) -> u64 {
+        at.saturating_duration_since(self.epoch).as_millis() as u64
+    }
+
+    /// Our instant for the peer's `millis`.
+    fn to_local(&self, peer_millis: u64) -> Instant {
+        let (offset, _) = self.offset.unwrap_or_else(|| {
+            log::warn!("no clock sync with the peer yet");
+            (0, 0)
+        });
+        let local = (peer_millis as i64 - offset).max(0) as u64;
+        self.epoch + Duration::from_millis(local)
+    }
+
+    fn send(&mut self, message: NetMessage) {
+        self.outbox.push_back((self.next_seq, message));
+        self.next_seq += 1;
+    }
+
+    /// Read every datagram waiting on the socket, returning the reliable
+    /// messages that are next in order.
+    fn receive(&mut self) -> Vec<NetMessage> {
+        let mut delivered = Vec::new();
+        let mut buf = [0; 65536];
+        loop {
+            let (len, from) = match self.socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                // e.g. the peer's port not open yet
+                Err(e) => {
+                    log::trace!("network receive: {e}");
+                    continue;
+                }
+            };
+            match self.peer {
+                Some(peer) if peer != from => {
+                    log::debug!(?from, "ignoring a datagram from a stranger");
+                    continue;
+                }
+                Some(_) => {}
+                None => {
+                    log::info!(peer = ?from, "peer joined");
+                    self.peer = Some(from);
+                    self.needs_setup = true;
+                    self.shared = None;
+                }
+            }
+            let packet: Packet = match std::str::from_utf8(&buf[..len])
+                .map_err(|e| e.to_string())
+                .and_then(|text| ron::from_str(text).map_err(|e| e.to_string()))
+            {
+                Ok(packet) => packet,
+                Err(e) => {
+                    log::warn!("bad datagram from the peer: {e}");
+                    continue;
+                }
+            };
+
+            let now = Instant::now();
+            self.last_heard = Some(now);
+            self.outbox.retain(|(seq, _)| *seq > packet.ack);
+            for (seq, message) in packet.messages {
+                if seq == self.received + 1 {
+                    self.received = seq;
+                    delivered.push(message);
+                }
+            }
+            for sync in packet.sync {
+                match sync {
+                    ClockSync::Ping(sent) => {
+                        self.sync.push(ClockSync::Pong(sent, self.millis(now)));
+                    }
+                    ClockSync::Pong(sent, peer_received) => {
+                        let rtt = self.millis(now).saturating_sub(sent);
+                        self.rtt = Some(Duration::from_millis(rtt));
+                        let offset = peer_received as i64 - (sent + rtt / 2) as i64;
+                        if self.offset.is_none_or(|(_, best)| rtt <= best) {
+                            self.offset = Some((offset, rtt));
+                        }
+                    }
+                }
+            }
+        }
+        delivered
+    }
+
+    /// Send what is waiting, and a ping when one is due.
+    fn flush(&mut self) {
+        let Some(peer) = self.peer else {
+            return;
+        };
+        let now = Instant::now();
+        if self
+            .last_ping
+            .is_none_or(|ping| now - ping >= PING_INTERVAL)
+        {
+            self.last_ping = Some(now);
+            self.sync.push(ClockSync::Ping(self.millis(now)));
+        }
+        // nothing new to say; the next ping keeps the peer's acks coming
+        if self.outbox.is_empty() && self.sync.is_empty() {
+            return;
+        }
+
+        let packet = Packet {
+            ack: self.received,
+            messages: self.outbox.iter().take(MAX_BATCH).cloned().collect(),
+            sync: std::mem::take(&mut self.sync),
+        };
+        let text = match ron::to_string(&packet) {
+            Ok(text) => text,
+            Err(e) => {
+                log::error!("could not encode a datagram: {e}");
+                return;
+            }
+        };
+        if let Err(e) = self.socket.send_to(text.as_bytes(), peer) {
+            log::trace!("network send: {e}");
+        }
+    }
+
+    /// Compare the peer's judgements with ours of its player, as far as both
+    /// have come.
+    fn compare_judgements(&mut self) {
+        while !self.remote_judgements.is_empty() && !self.replayed_judgements.is_empty() {
+            let remote = self.remote_judgements.pop_front().unwrap();
+            let replayed = self.replayed_judgements.pop_front().unwrap();
+            if remote != replayed {
+                self.desyncs += 1;
+                log::warn!(?remote, ?replayed, "judgement out of sync with the peer");
+            }
+        }
+    }
+}
+
+/// Whether inputs are shared with the peer in `state`; before a match the
+/// players' picks are, see `NetMessage::Player`.
+fn is_shared(state: &AppState) -> bool {
+    matches!(state, AppState::InGame)
+}
+
+#[derive(Debug, Component)]
+struct NetStatusText;
+
+fn setup_net_status(mut commands: Commands) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: STATUS_FONT_SIZE,
+                color: Color::rgb(0.9, 0.9, 0.9),
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(5.),
+            right: Val::Px(5.),
+            ..default()
+        }),
+        ZIndex::Global(100),
+        NetStatusText,
+    ));
+}
+
+#[allow(clippy::too_many_arguments)]
+fn receive_system(
+    mut session: ResMut<NetSession>,
+    mut player_cmd_evt_w: EventWriter<PlayerCommandEvent>,
+    mut modes: ResMut<GameModes>,
+    mut seed: ResMut<MatchSeed>,
+    mut settings: ResMut<PlayerSettings>,
+    latency: Res<Latency>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let team = session.remote_team();
+    // the state the messages after a `Begin` or `Rematch` are played in
+    let mut state = *state.get();
+    for message in session.receive() {
+        match message {
+            NetMessage::Setup {
+                seed: new_seed,
+                mode,
+            } => {
+                log::info!(seed = new_seed, mode, "match setup from the host");
+                seed.0 = new_seed;
+                modes.selected = mode;
+            }
+            NetMessage::Player { setting, ready } => {
+                // left over from before a match the host just began
+                if state == AppState::InGame {
+                    continue;
+                }
+                setting.apply(settings.get_mut(team));
+                session.peer_ready = ready;
+            }
+            NetMessage::Begin {
+                seed: new_seed,
+                mode,
+                settings: new_settings,
+            } => {
+                log::info!(seed = new_seed, mode, "match begun by the host");
+                seed.0 = new_seed;
+                modes.selected = mode;
+                settings.set_count(new_settings.len());
+                for (team, setting) in (1..).zip(&new_settings) {
+                    setting.apply(settings.get_mut(team));
+                }
+                state = AppState::InGame;
+                next_state.set(state);
+            }
+            NetMessage::Rematch => {
+                session.peer_rematch = true;
+                if state == AppState::Results && session.rematch {
+                    state = AppState::InGame;
+                    next_state.set(state);
+                }
+            }
+            NetMessage::Start { at } => {
+                session.start_at = Some(session.to_local(at));
+            }
+            NetMessage::Command {
+                cmd,
+                pressed,
+                tap,
+                time,
+            } => {
+                if !is_shared(&state) {
+                    continue;
+                }
+                // judged by `check_key_down` as if it had been read here
+                player_cmd_evt_w.send(PlayerCommandEvent {
+                    team,
+                    cmd,
+                    pressed,
+                    tap,
+                    time: latency.read_time(time),
+                });
+            }
+            NetMessage::Judged(judgement) => {
+                session.remote_judgements.push_back(judgement);
+            }
+        }
+    }
+}
+
+/// The host picks when the song starts, far enough ahead for the peer to
+/// hear of it in time.
+fn start_match(mut session: ResMut<NetSession>) {
+    // the players pick again, and ask again, for the match after this one
+    session.shared = None;
+    session.peer_ready = false;
+    session.rematch = false;
+    session.peer_rematch = false;
+    session.desyncs = 0;
+    session.remote_judgements.clear();
+    session.replayed_judgements.clear();
+    if session.is_host() {
+        let at = Instant::now() + START_DELAY;
+        session.start_at = Some(at);
+        let at = session.millis(at);
+        session.send(NetMessage::Start { at });
+    }
+}
+
+fn stop_match(mut session: ResMut<NetSession>) {
+    session.start_at = None;
+}
+
+/// Both machines count song position from the shared start, however late
+/// their music starts playing.
+fn start_clock_system(session: Res<NetSession>, mut clock: ResMut<SongClock>) {
+    if let Some(at) = session.start_at.filter(|_| session.started()) {
+        clock.start(at);
+    }
+}
+
+/// The host sends the mode and seed to a new peer and again when the mode
+/// changes.
+fn match_setup_system(
+    mut session: ResMut<NetSession>,
+    modes: Res<GameModes>,
+    seed: Res<MatchSeed>,
+) {
+    if !session.is_host() || session.peer.is_none() {
+        return;
+    }
+    if session.needs_setup || modes.is_changed() {
+        session.needs_setup = false;
+        session.send(NetMessage::Setup {
+            seed: seed.0,
+            mode: modes.selected,
+        });
+    }
+}
+
+fn send_command_system(
+    mut session: ResMut<NetSession>,
+    mut player_cmd_evt: EventReader<PlayerCommandEvent>,
+    latency: Res<Latency>,
+    state: Res<State<AppState>>,
+) {
+    for e in player_cmd_evt.read() {
+        if e.team != session.local_team() || !is_shared(state.get()) {
+            continue;
+        }
+        session.send(NetMessage::Command {
+            cmd: e.cmd,
+            pressed: e.pressed,
+            tap: e.tap,
+            time: latency.input_time(e.time),
+        });
+    }
+}
+
+fn judgement_exchange_system(
+    mut session: ResMut<NetSession>,
+    mut judgement_evt: EventReader<JudgementEvent>,
+) {
+    for e in judgement_evt.read() {
+        if e.team == session.local_team() {
+            session.send(NetMessage::Judged(e.judgement));
+        } else if e.team == session.remote_team() {
+            session.replayed_judgements.push_back(e.judgement);
+        }
+    }
+    session.compare_judgements();
+}
+
+fn flush_system(mut session: ResMut<NetSession>) {
+    session.flush();
+}
+
+fn net_status_update_system(
+    session: Res<NetSession>,
+    mut query: Query<&mut Text, With<NetStatusText>>,
+) {
+    let status = if session.is_connected() {
+        let ping = session.rtt.unwrap_or_default().as_millis();
+        let mut status = format!(
+            "P{} (you) vs P{}  ping {ping}ms",
+            session.local_team(),
+            session.remote_team()
+        );
+        if session.desyncs > 0 {
+            status += &format!("  {} out of sync", session.desyncs);
+        }
+        if session.rematch && !session.peer_rematch {
+            status += &format!("  waiting for P{} to rematch", session.remote_team());
+        }
+        status
+    } else if session.last_heard.is_some() {
+        "Connection to the peer lost".to_string()
+    } else if session.is_host() {
+        format!(
+            "Waiting for a peer on port {}",
+            session.socket.local_addr().map_or(0, |a| a.port())
+        )
+    } else {
+        "Joining...".to_string()
+    };
+    for mut text in &mut query {
+        text.sections[0].value = status.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair() -> (NetSession, NetSession) {
+        // any free port
+        let host = NetSession::bind(NetRole::Host { port: 0 }).unwrap();
+        let port = host.socket.local_addr().unwrap().port();
+        let join = NetSession::bind(NetRole::Join {
+            host: SocketAddr::from(([127, 0, 0, 1], port)),
+        })
+        .unwrap();
+        (host, join)
+    }
+
+    /// Flush both sides and receive what they sent, a few times over.
+    fn exchange(a: &mut NetSession, b: &mut NetSession) -> (Vec<NetMessage>, Vec<NetMessage>) {
+        let (mut to_a, mut to_b) = (Vec::new(), Vec::new());
+        for _ in 0..10 {
+            a.flush();
+            b.flush();
+            std::thread::sleep(Duration::from_millis(5));
+            to_a.extend(a.receive());
+            to_b.extend(b.receive());
+        }
+        (to_a, to_b)
+    }
+
+    #[test]
+    fn messages_arrive_once_and_in_order() {
+        let (mut host, mut join) = pair();
+        for key in [
+            PlayerCommand::Hit1,
+            PlayerCommand::Hit2,
+            PlayerCommand::Hit3,
+        ] {
+            join.send(NetMessage::Command {
+                cmd: key,
+                pressed: true,
+                tap: false,
+                time: Duration::from_millis(1500),
+            });
+        }
+        let (to_host, _) = exchange(&mut host, &mut join);
+
+        let keys: Vec<_> = to_host
+            .iter()
+            .map(|message| match message {
+                NetMessage::Command { cmd, .. } => format!("{cmd:?}"),
+                other => panic!("unexpected {other:?}"),
+            })
+            .collect();
+        assert_eq!(keys, ["Hit1", "Hit2", "Hit3"]);
+        assert!(join.outbox.is_empty());
+        assert!(host.needs_setup);
+    }
+
+    #[test]
+    fn picks_are_sent_when_they_change() {
+        let (mut host, mut join) = pair();
+        let mut setting = PlayerSetting::default();
+        join.share_player(&setting, false);
+        join.share_player(&setting, false);
+        setting.difficulty = Difficulty::Hard;
+        join.share_player(&setting, true);
+        let mut settings = PlayerSettings::default();
+        settings.get_mut(2).scroll_speed = 2.;
+        host.begin(7, 1, &settings);
+        let (to_join, to_host) = exchange(&mut join, &mut host);
+
+        let picks: Vec<_> = to_host
+            .iter()
+            .map(|message| match message {
+                NetMessage::Player { setting, ready } => (setting.difficulty, *ready),
+                other => panic!("unexpected {other:?}"),
+            })
+            .collect();
+        assert_eq!(
+            picks,
+            [(Difficulty::Normal, false), (Difficulty::Hard, true)]
+        );
+        match &to_join[..] {
+            [NetMessage::Begin {
+                seed: 7,
+                mode: 1,
+                settings,
+            }] => {
+                assert_eq!(settings.len(), 2);
+                assert_eq!(settings[1].scroll_speed, 2.);
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn clocks_sync_over_localhost() {
+        let (mut host, mut join) = pair();
+        exchange(&mut host, &mut join);
+
+        // both epochs were taken within moments of each other
+        let (offset, rtt) = join.offset.unwrap();
+        assert!(offset.abs() < 50, "offset {offset}ms");
+        assert!(rtt < 50, "round trip {rtt}ms");
+        let at = host.millis(Instant::now()) + 3000;
+        let local = join.to_local(at);
+        let expected = host.epoch + Duration::from_millis(at);
+        assert!(local.max(expected) - local.min(expected) < Duration::from_millis(50));
+    }
+}
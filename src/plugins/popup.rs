@@ -0,0 +1,164 @@
+use crate::plugins::combat::CombatEvent;
+use crate::plugins::sound_player::{hit_ring, HitResult};
+use crate::plugins::stats::{Judgement, JudgementEvent};
+use crate::AppState;
+use bevy::prelude::*;
+use bevy_tweening::lens::{TextColorLens, TransformPositionLens};
+use bevy_tweening::{Animator, EaseFunction, Tween};
+use std::time::Duration;
+
+const POPUP_DURATION: Duration = Duration::from_millis(600);
+/// Popups rise from this far above the hit ring...
+const POPUP_START: Vec2 = Vec2::new(0., 90.);
+/// ...to this far.
+const POPUP_END: Vec2 = Vec2::new(0., 150.);
+const POPUP_FONT_SIZE: f32 = 40.;
+const ACTION_POPUP_OFFSET: Vec2 = Vec2::new(0., 100.);
+/// Offsets within this many milliseconds are not called early or late.
+const EARLY_LATE_THRESHOLD: f32 = 10.;
+
+/// Show each judgement above the hit ring it happened on.
+#[derive(Debug)]
+pub struct JudgementPopupPlugin;
+
+impl Plugin for JudgementPopupPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (spawn_popup_system, spawn_action_popup_system).run_if(in_state(AppState::InGame)),
+        )
+        .add_systems(OnExit(AppState::InGame), clear_popups);
+    }
+}
+
+#[derive(Debug, Component)]
+struct JudgementPopup {
+    team: i32,
+}
+
+#[derive(Debug, Component)]
+struct ActionPopup;
+
+fn popup_text(judgement: Judgement) -> &'static str {
+    match judgement {
+        Judgement::Hit(HitResult::Perfect) => "PERFECT",
+        Judgement::Hit(HitResult::Good) => "GOOD",
+        Judgement::Hit(HitResult::Ok) => "OK",
+        Judgement::Hit(HitResult::Miss) => "MISS",
+        Judgement::WrongKey => "WRONG",
+    }
+}
+
+pub(crate) fn judgement_color(judgement: Judgement) -> Color {
+    match judgement {
+        Judgement::Hit(HitResult::Perfect) => Color::rgb(1., 0.85, 0.2),
+        Judgement::Hit(HitResult::Good) => Color::rgb(0.3, 0.9, 0.4),
+        Judgement::Hit(HitResult::Ok) => Color::rgb(0.4, 0.7, 1.),
+        Judgement::Hit(HitResult::Miss) => Color::rgb(0.9, 0.2, 0.2),
+        Judgement::WrongKey => Color::rgb(0.8, 0.3, 0.9),
+    }
+}
+
+fn spawn_popup_system(
+    mut commands: Commands,
+    mut judgement_evt: EventReader<JudgementEvent>,
+    popup_query: Query<(Entity, &JudgementPopup)>,
+) {
+    for e in judgement_evt.read() {
+        // only the latest judgement of a team stays on screen
+        for (ent, popup) in &popup_query {
+            if popup.team == e.team {
+                commands.entity(ent).despawn();
+            }
+        }
+
+        let label = popup_text(e.judgement);
+        let color = judgement_color(e.judgement);
+        let timing = match e.offset {
+            _ if e.judgement == Judgement::Hit(HitResult::Perfect) => "",
+            Some(offset) if offset < -EARLY_LATE_THRESHOLD => "\nEARLY",
+            Some(offset) if offset > EARLY_LATE_THRESHOLD => "\nLATE",
+            _ => "",
+        };
+
+        let ring = hit_ring(e.team);
+        commands.spawn((
+            popup_bundle(
+                format!("{label}{timing}"),
+                color,
+                ring + POPUP_START,
+                ring + POPUP_END,
+            ),
+            JudgementPopup { team: e.team },
+        ));
+    }
+}
+
+/// Combat actions pop up higher, above the judgements.
+fn spawn_action_popup_system(mut commands: Commands, mut combat_evt: EventReader<CombatEvent>) {
+    for e in combat_evt.read() {
+        let ring = hit_ring(e.team);
+        commands.spawn((
+            popup_bundle(
+                format!("{}!", e.action),
+                Color::WHITE,
+                ring + POPUP_START + ACTION_POPUP_OFFSET,
+                ring + POPUP_END + ACTION_POPUP_OFFSET,
+            ),
+            ActionPopup,
+        ));
+    }
+}
+
+/// Text rising from `from` to `to` while it fades out.
+fn popup_bundle(text: String, color: Color, from: Vec2, to: Vec2) -> impl Bundle {
+    let from = from.extend(40.);
+    let to = to.extend(40.);
+    let pos_tween = Tween::new(
+        EaseFunction::QuadraticOut,
+        POPUP_DURATION,
+        TransformPositionLens {
+            start: from,
+            end: to,
+        },
+    );
+    // despawned by `JumpImagePlugin` once faded out
+    let color_tween = Tween::new(
+        EaseFunction::QuadraticIn,
+        POPUP_DURATION,
+        TextColorLens {
+            start: color,
+            end: color.with_a(0.),
+            section: 0,
+        },
+    )
+    .with_completed_event(0);
+
+    (
+        Text2dBundle {
+            text: Text::from_section(
+                text,
+                TextStyle {
+                    font_size: POPUP_FONT_SIZE,
+                    color,
+                    ..default()
+                },
+            )
+            .with_alignment(TextAlignment::Center),
+            transform: Transform::from_translation(from),
+            ..default()
+        },
+        Animator::new(pos_tween),
+        Animator::new(color_tween),
+    )
+}
+
+fn clear_popups(
+    mut commands: Commands,
+    judgement_query: Query<Entity, With<JudgementPopup>>,
+    action_query: Query<Entity, With<ActionPopup>>,
+) {
+    for ent in judgement_query.iter().chain(&action_query) {
+        commands.entity(ent).despawn();
+    }
+}
@@ -0,0 +1,229 @@
+use crate::config::{load_config, load_settings, save_settings, Rank, RankConfig};
+use crate::plugins::character_selection::PlayerSettings;
+use crate::plugins::chart::{Chart, SelectedChart};
+use crate::plugins::life::LifeGauges;
+use crate::plugins::net::NetSession;
+use crate::plugins::score::CounterNumber;
+use crate::plugins::stats::{MatchStats, TeamStats};
+use crate::plugins::tug::TugOfWar;
+use crate::AppState;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// WIN/LOSE art covers one half of the screen each in a two-team match.
+const RESULT_X: f32 = 480.;
+const TEXT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+const FONT_SIZE: f32 = 30.;
+const RANK_FONT_SIZE: f32 = 100.;
+/// Stats text shrinks with the art, but no further than this.
+const MIN_TEXT_SCALE: f32 = 0.6;
+const RECORDS_FILE: &str = "records.ron";
+
+/// Who won the match just played, and the way back into the next one.
+#[derive(Debug)]
+pub struct ResultsPlugin;
+
+impl Plugin for ResultsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(load_config::<RankConfig>("ranks.ron"))
+            .add_systems(OnEnter(AppState::Results), (setup_results, save_records))
+            .add_systems(Update, results_controls.run_if(in_state(AppState::Results)))
+            .add_systems(OnExit(AppState::Results), cleanup_results);
+    }
+}
+
+#[derive(Debug, Component)]
+struct ResultsTag;
+
+/// Best plays of each song, keyed by its music path.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Records(BTreeMap<String, Vec<Record>>);
+
+/// The best play of one track at one difficulty.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Record {
+    track: String,
+    difficulty: String,
+    score: usize,
+    accuracy: f32,
+    rank: Rank,
+}
+
+fn rank_of(rank_config: &RankConfig, stats: &TeamStats) -> Rank {
+    rank_config.rank(stats.accuracy(), stats.miss + stats.wrong_key)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn setup_results(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    counter: Res<CounterNumber>,
+    settings: Res<PlayerSettings>,
+    stats: Res<MatchStats>,
+    rank_config: Res<RankConfig>,
+    life: Res<LifeGauges>,
+    tug: Res<TugOfWar>,
+) {
+    commands.spawn((
+        SpriteBundle {
+            texture: asset_server.load("images/background.png"),
+            transform: Transform {
+                translation: Vec3::new(0., 0., -10.),
+                ..default()
+            },
+            ..default()
+        },
+        ResultsTag,
+    ));
+
+    // one column per team, the art shrunk to fit when there are more than two
+    let teams = settings.teams().count();
+    let column = 1920. / teams as f32;
+    let scale = (column / (RESULT_X * 2.)).min(1.);
+    let scores: Vec<_> = settings
+        .teams()
+        .map(|team| {
+            let x = -960. + (team as f32 - 0.5) * column;
+            (team, counter.get(team), x)
+        })
+        .collect();
+    // a knockout decides the match; otherwise a team that failed only wins if
+    // every team did
+    let standing =
+        |team: i32, score: usize| (tug.knocked_out_by(team), !life.get(team).failed, score);
+    let best = scores
+        .iter()
+        .map(|(team, score, _)| standing(*team, *score))
+        .max()
+        .unwrap();
+    let text_style = TextStyle {
+        font_size: FONT_SIZE,
+        color: TEXT_COLOR,
+        ..default()
+    };
+    for (team, score, x) in scores {
+        // a draw is a win for both sides
+        let img = if standing(team, score) == best {
+            "images/ui/game/WIN.png"
+        } else {
+            "images/ui/game/LOSE.png"
+        };
+        commands.spawn((
+            SpriteBundle {
+                texture: asset_server.load(img),
+                transform: Transform::from_xyz(x, 0., 0.).with_scale(Vec3::splat(scale)),
+                ..default()
+            },
+            ResultsTag,
+        ));
+        commands.spawn((
+            Text2dBundle {
+                text: Text::from_section(
+                    rank_of(&rank_config, stats.get(team)).to_string(),
+                    TextStyle {
+                        font_size: RANK_FONT_SIZE,
+                        color: TEXT_COLOR,
+                        ..default()
+                    },
+                ),
+                transform: Transform::from_xyz(x, 340., 1.),
+                ..default()
+            },
+            ResultsTag,
+        ));
+
+        let difficulty = settings.get(team).difficulty;
+        let note = if tug.knocked_out_by(team) {
+            "  KNOCKOUT"
+        } else if life.get(team).failed {
+            "  FAILED"
+        } else {
+            ""
+        };
+        commands.spawn((
+            Text2dBundle {
+                text: Text::from_section(
+                    format!(
+                        "P{team}  Score: {score}{note}\n{}\n{difficulty}\n{}",
+                        stats.get(team),
+                        difficulty.windows()
+                    ),
+                    text_style.clone(),
+                )
+                .with_alignment(TextAlignment::Center),
+                transform: Transform::from_xyz(x, -200., 1.)
+                    .with_scale(Vec3::splat(scale.max(MIN_TEXT_SCALE))),
+                ..default()
+            },
+            ResultsTag,
+        ));
+    }
+
+    commands.spawn((
+        TextBundle::from_section("[R] rematch  [M] menu", text_style).with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(5.),
+            left: Val::Px(5.),
+            ..default()
+        }),
+        ResultsTag,
+    ));
+}
+
+/// Keep each team's play if it beats the best one of its track and difficulty.
+fn save_records(
+    counter: Res<CounterNumber>,
+    settings: Res<PlayerSettings>,
+    stats: Res<MatchStats>,
+    rank_config: Res<RankConfig>,
+    selected_chart: Res<SelectedChart>,
+    charts: Res<Assets<Chart>>,
+) {
+    let Some(chart) = charts.get(&selected_chart.0) else {
+        return;
+    };
+
+    let mut records: Records = load_settings(RECORDS_FILE);
+    let song_records = records.0.entry(chart.song.music.clone()).or_default();
+    for team in settings.teams() {
+        let score = counter.get(team);
+        let setting = settings.get(team);
+        let record = Record {
+            track: chart.track(setting.track).name.clone(),
+            difficulty: setting.difficulty.to_string(),
+            score,
+            accuracy: stats.get(team).accuracy(),
+            rank: rank_of(&rank_config, stats.get(team)),
+        };
+        let best = song_records
+            .iter_mut()
+            .find(|r| r.track == record.track && r.difficulty == record.difficulty);
+        match best {
+            Some(best) if best.score >= record.score => {}
+            Some(best) => *best = record,
+            None => song_records.push(record),
+        }
+    }
+    save_settings(RECORDS_FILE, &records);
+}
+
+/// Over the network, a rematch starts once both players asked for one.
+fn results_controls(
+    keys: Res<Input<KeyCode>>,
+    net: Option<ResMut<NetSession>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if keys.just_pressed(KeyCode::R) && net.is_none_or(|mut net| net.request_rematch()) {
+        next_state.set(AppState::InGame);
+    }
+    if keys.just_pressed(KeyCode::M) {
+        next_state.set(AppState::Menu);
+    }
+}
+
+fn cleanup_results(mut commands: Commands, query: Query<Entity, With<ResultsTag>>) {
+    for ent in &query {
+        commands.entity(ent).despawn_recursive();
+    }
+}
@@ -1,14 +1,187 @@
-use bevy::prelude::*;
+use bevy::{log, prelude::*};
 
+use crate::config::{
+    load_config, ComboCurve, ComboTier, CrowdThresholds, GameMode, GameModes, ImageKey,
+    JudgementScores, ScoringKind,
+};
+use crate::plugins::character_selection::PlayerSettings;
+use crate::plugins::clock::SongClock;
+use crate::plugins::effects::SpecialEffects;
+use crate::plugins::ex::ExGauges;
+use crate::plugins::sound_player::HitResult;
+use crate::plugins::{team_row, team_side};
 use crate::AppState;
 
 #[derive(Debug, Event)]
 pub struct GenEvent(pub i32, pub i32); //player/img
 
+/// Scoring of the selected game mode, set up when a match starts.
 #[derive(Resource)]
 pub struct ScoreSetting {
-    pub basic_score: usize,
-    pub combo_score: usize,
+    /// Formula of the current mode.
+    pub rule: Box<dyn ScoringRule>,
+    /// Combo tiers, lowest first.
+    pub combo_tiers: Vec<ComboTier>,
+    pub crowd: CrowdThresholds,
+}
+
+impl Default for ScoreSetting {
+    fn default() -> Self {
+        Self::from_mode(&GameMode::default())
+    }
+}
+
+/// A formula turning hits into points; each mode can bring its own.
+pub trait ScoringRule: Send + Sync {
+    /// Points for a successful hit judged `result`, made with `combo` hits
+    /// already chained.
+    fn points(&self, result: HitResult, combo: usize) -> usize;
+
+    /// Combo after a successful hit.
+    fn next_combo(&self, combo: usize) -> usize {
+        combo + 1
+    }
+}
+
+/// The judgement's base score plus a bonus growing with the combo, as set in
+/// the mode's config.
+#[derive(Debug, Clone, Copy)]
+pub struct ComboBonusRule {
+    pub scores: JudgementScores,
+    pub curve: ComboCurve,
+}
+
+fn base_points(scores: &JudgementScores, result: HitResult) -> usize {
+    match result {
+        HitResult::Perfect => scores.perfect,
+        HitResult::Good => scores.good,
+        HitResult::Ok => scores.ok,
+        HitResult::Miss => 0,
+    }
+}
+
+impl ScoringRule for ComboBonusRule {
+    fn points(&self, result: HitResult, combo: usize) -> usize {
+        let base = base_points(&self.scores, result);
+        let combo = self.curve.cap.map_or(combo, |cap| combo.min(cap));
+        base + (combo as f32 * self.curve.bonus) as usize
+    }
+
+    fn next_combo(&self, combo: usize) -> usize {
+        combo + self.curve.step
+    }
+}
+
+/// The judgement's base score alone, however long the combo; the combo still
+/// reaches the mode's tiers.
+#[derive(Debug, Clone, Copy)]
+pub struct FlatRule {
+    pub scores: JudgementScores,
+}
+
+impl ScoringRule for FlatRule {
+    fn points(&self, result: HitResult, _combo: usize) -> usize {
+        base_points(&self.scores, result)
+    }
+}
+
+/// What one attack did to a team's score.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ScoreOutcome {
+    pub gained: usize,
+    /// Crowd members the attack brings out, `GenEvent(team, 1)`.
+    pub people: usize,
+    /// Cats the attack brings out, `GenEvent(team, 2)`.
+    pub cats: usize,
+}
+
+impl ScoreSetting {
+    pub fn from_mode(mode: &GameMode) -> Self {
+        let rule: Box<dyn ScoringRule> = match mode.scoring {
+            ScoringKind::ComboBonus => Box::new(ComboBonusRule {
+                scores: mode.scores,
+                curve: mode.combo,
+            }),
+            ScoringKind::Flat => Box::new(FlatRule {
+                scores: mode.scores,
+            }),
+        };
+        Self {
+            rule,
+            combo_tiers: mode.combo_tiers.clone(),
+            crowd: mode.crowd,
+        }
+    }
+
+    /// Tier reached at `combo`, 0 below the first one.
+    pub fn tier(&self, combo: usize) -> usize {
+        self.combo_tiers.iter().filter(|t| combo >= t.combo).count()
+    }
+
+    pub fn multiplier(&self, combo: usize) -> f32 {
+        match self.tier(combo) {
+            0 => 1.,
+            tier => self.combo_tiers[tier - 1].multiplier,
+        }
+    }
+
+    /// Score one attack of a team with `score` and `combo`. A successful one
+    /// gains the rule's points times the combo tier's multiplier and `boost`;
+    /// a `HitResult::Miss` breaks the combo unless it is `shielded`.
+    pub fn score_attack(
+        &self,
+        score: &mut usize,
+        combo: &mut usize,
+        result: HitResult,
+        shielded: bool,
+        boost: usize,
+    ) -> ScoreOutcome {
+        if result == HitResult::Miss {
+            if !shielded {
+                *combo = 0;
+            }
+            return ScoreOutcome::default();
+        }
+
+        let points = self.rule.points(result, *combo);
+        // the crowd grows with the points before any multiplier
+        let crossed = |every: usize| (*score + points) / every.max(1) - *score / every.max(1);
+        let people = crossed(self.crowd.people_every);
+        let cats = if crossed(self.crowd.cats_every) >= self.crowd.cats_min {
+            people
+        } else {
+            0
+        };
+        let gained = (points as f32 * self.multiplier(*combo) * boost as f32) as usize;
+        *score += gained;
+        *combo = self.rule.next_combo(*combo);
+        ScoreOutcome {
+            gained,
+            people,
+            cats,
+        }
+    }
+
+    /// What to announce when a team's combo goes from `before` to `after`: a
+    /// new tier, or a break of a combo that had reached one.
+    pub fn combo_event(&self, team: i32, before: usize, after: usize) -> Option<ComboEvent> {
+        if after == 0 {
+            (self.tier(before) > 0).then_some(ComboEvent::Break {
+                team,
+                combo: before,
+            })
+        } else {
+            let tier = self.tier(after);
+            (tier > self.tier(before)).then_some(ComboEvent::Tier { team, tier })
+        }
+    }
+}
+
+/// A team's combo moving into a higher tier, or breaking after reaching one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Event)]
+pub enum ComboEvent {
+    Tier { team: i32, tier: usize },
+    Break { team: i32, combo: usize },
 }
 
 #[derive(Debug)]
@@ -18,28 +191,17 @@ impl Plugin for ScorePlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<GenEvent>()
             .add_event::<AttackEvent>()
-            .insert_resource(ScoreSetting {
-                basic_score: 3,
-                combo_score: 1,
-            })
+            .add_event::<ComboEvent>()
+            .insert_resource(load_config::<GameModes>("modes.ron"))
+            .init_resource::<ScoreSetting>()
             .add_systems(OnEnter(AppState::InGame), setup_in_game_ui)
+            .add_systems(OnExit(AppState::InGame), cleanup_in_game_ui)
             .add_systems(Update, score_system.run_if(in_state(AppState::InGame)))
-            .insert_resource(CounterNumber {
-                score1: 0,
-                score2: 0,
-            })
-            .insert_resource(ComboNumber {
-                score1: 0,
-                score2: 0,
-            })
+            .init_resource::<CounterNumber>()
+            .init_resource::<ComboNumber>()
             .add_systems(
                 Update,
-                (
-                    counter1_update_system,
-                    counter2_update_system,
-                    combo1_update_system,
-                    combo2_update_system,
-                )
+                (counter_update_system, combo_update_system)
                     .chain()
                     .run_if(in_state(AppState::InGame)),
             );
@@ -49,253 +211,424 @@ impl Plugin for ScorePlugin {
 const COUNTER_COLOR: Color = Color::rgb(1.0, 0.5, 0.5);
 const SCOREBOARD_FONT_SIZE: f32 = 40.0;
 
+/// Everything `setup_in_game_ui` spawns.
 #[derive(Component)]
-struct CounterText1;
+struct ScoreUi;
+
+/// How far down each further pair of teams has its scoreboard.
+const SCOREBOARD_ROW_HEIGHT: f32 = 130.;
 
 #[derive(Component)]
-struct CounterText2;
+struct CounterText {
+    team: i32,
+}
 
+/// A team's hit as `(team, judgement)`; anything that breaks the combo, wrong
+/// keys and decoys included, is a `HitResult::Miss`.
 #[derive(Debug, Event)]
-pub struct AttackEvent(pub i32, pub bool);
+pub struct AttackEvent(pub i32, pub HitResult);
 
 #[derive(Component)]
-struct ComboText1;
+struct ComboText {
+    team: i32,
+}
 
-#[derive(Component)]
-struct ComboText2;
+/// Score of each team.
+#[derive(Debug, Default, Resource)]
+pub struct CounterNumber(pub(crate) Vec<usize>);
 
-#[derive(Resource)]
-pub struct CounterNumber {
-    pub score1: usize,
-    pub score2: usize,
+impl CounterNumber {
+    pub fn get(&self, team: i32) -> usize {
+        self.0.get((team - 1) as usize).copied().unwrap_or(0)
+    }
+
+    fn get_mut(&mut self, team: i32) -> Option<&mut usize> {
+        self.0.get_mut((team - 1) as usize)
+    }
 }
 
-#[derive(Resource)]
-pub struct ComboNumber {
-    pub score1: usize,
-    pub score2: usize,
+/// Current combo of each team.
+#[derive(Debug, Default, Resource)]
+pub struct ComboNumber(pub(crate) Vec<usize>);
+
+impl ComboNumber {
+    pub fn get(&self, team: i32) -> usize {
+        self.0.get((team - 1) as usize).copied().unwrap_or(0)
+    }
+
+    fn get_mut(&mut self, team: i32) -> Option<&mut usize> {
+        self.0.get_mut((team - 1) as usize)
+    }
+}
+
+/// Where `team`'s scoreboard goes: odd teams down the left edge, even teams
+/// down the right.
+pub(crate) fn scoreboard_style(team: i32, top: f32) -> Style {
+    let mut style = Style {
+        position_type: PositionType::Absolute,
+        top: Val::Px(top + team_row(team) as f32 * SCOREBOARD_ROW_HEIGHT),
+        ..default()
+    };
+    if team_side(team) < 0. {
+        style.left = Val::Px(5.);
+    } else {
+        style.right = Val::Px(5.);
+    }
+    style
 }
 
+#[allow(clippy::too_many_arguments)]
 fn setup_in_game_ui(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
+    settings: Res<PlayerSettings>,
+    modes: Res<GameModes>,
+    mut setting: ResMut<ScoreSetting>,
+    mut counter: ResMut<CounterNumber>,
+    mut combo: ResMut<ComboNumber>,
     mut evt_w: EventWriter<GenEvent>,
 ) {
+    let mode = modes.current();
+    log::info!(mode = mode.name, "game mode");
+    *setting = ScoreSetting::from_mode(&mode);
+
+    // a rematch starts from scratch
+    let teams = settings.teams().count();
+    *counter = CounterNumber(vec![0; teams]);
+    *combo = ComboNumber(vec![0; teams]);
+
     let background = asset_server.load("images/background.png");
-    commands.spawn(SpriteBundle {
-        texture: background,
-        transform: Transform {
-            translation: Vec3::new(0., 0., -10.),
-            ..default()
-        },
-        ..Default::default()
-    });
-    commands.spawn((
-        TextBundle::from_sections([
-            TextSection::new(
-                "Player1\nScore: ",
-                TextStyle {
-                    font_size: SCOREBOARD_FONT_SIZE,
-                    color: COUNTER_COLOR,
-                    ..default()
-                },
-            ),
-            TextSection::from_style(TextStyle {
-                font_size: SCOREBOARD_FONT_SIZE,
-                color: COUNTER_COLOR,
-                ..default()
-            }),
-        ])
-        .with_style(Style {
-            position_type: PositionType::Absolute,
-            top: Val::Px(5.0),
-            left: Val::Px(5.0),
-            ..default()
-        }),
-        CounterText1,
-    ));
     commands.spawn((
-        TextBundle::from_sections([
-            TextSection::new(
-                "Combo: ",
-                TextStyle {
-                    font_size: SCOREBOARD_FONT_SIZE,
-                    color: COUNTER_COLOR,
-                    ..default()
-                },
-            ),
-            TextSection::from_style(TextStyle {
-                font_size: SCOREBOARD_FONT_SIZE,
-                color: COUNTER_COLOR,
-                ..default()
-            }),
-        ])
-        .with_style(Style {
-            position_type: PositionType::Absolute,
-            top: Val::Px(80.0),
-            left: Val::Px(5.0),
-            ..default()
-        }),
-        ComboText1,
-    ));
-    commands.spawn((TextBundle::from_sections([
-        TextSection::new(
-            "Player2:",
-            TextStyle {
-                font_size: SCOREBOARD_FONT_SIZE,
-                color: COUNTER_COLOR,
+        SpriteBundle {
+            texture: background,
+            transform: Transform {
+                translation: Vec3::new(0., 0., -10.),
                 ..default()
             },
-        ),
-        TextSection::from_style(TextStyle {
-            font_size: SCOREBOARD_FONT_SIZE,
-            color: COUNTER_COLOR,
-            ..default()
-        }),
-    ])
-    .with_style(Style {
-        position_type: PositionType::Absolute,
-        top: Val::Px(5.0),
-        right: Val::Px(5.0),
-        ..default()
-    }),));
-    commands.spawn((
-        TextBundle::from_sections([
-            TextSection::new(
-                "Score: ",
-                TextStyle {
-                    font_size: SCOREBOARD_FONT_SIZE,
-                    color: COUNTER_COLOR,
-                    ..default()
-                },
-            ),
-            TextSection::from_style(TextStyle {
-                font_size: SCOREBOARD_FONT_SIZE,
-                color: COUNTER_COLOR,
-                ..default()
-            }),
-        ])
-        .with_style(Style {
-            position_type: PositionType::Absolute,
-            top: Val::Px(40.0),
-            right: Val::Px(5.0),
-            ..default()
-        }),
-        CounterText2,
-    ));
-    commands.spawn((
-        TextBundle::from_sections([
-            TextSection::new(
-                "Combo: ",
-                TextStyle {
-                    font_size: SCOREBOARD_FONT_SIZE,
-                    color: COUNTER_COLOR,
-                    ..default()
-                },
-            ),
-            TextSection::from_style(TextStyle {
-                font_size: SCOREBOARD_FONT_SIZE,
-                color: COUNTER_COLOR,
-                ..default()
-            }),
-        ])
-        .with_style(Style {
-            position_type: PositionType::Absolute,
-            top: Val::Px(80.0),
-            right: Val::Px(5.0),
-            ..default()
-        }),
-        ComboText2,
+            ..Default::default()
+        },
+        ScoreUi,
     ));
-    evt_w.send(GenEvent(1, 3));
-    evt_w.send(GenEvent(2, 3));
+    let text_style = TextStyle {
+        font_size: SCOREBOARD_FONT_SIZE,
+        color: COUNTER_COLOR,
+        ..default()
+    };
+    for team in settings.teams() {
+        commands.spawn((
+            TextBundle::from_sections([
+                TextSection::new(format!("Player{team}\nScore: "), text_style.clone()),
+                TextSection::from_style(text_style.clone()),
+            ])
+            .with_style(scoreboard_style(team, 5.)),
+            CounterText { team },
+            ScoreUi,
+        ));
+        commands.spawn((
+            TextBundle::from_sections([
+                TextSection::new("Combo: ", text_style.clone()),
+                TextSection::from_style(text_style.clone()),
+            ])
+            .with_style(scoreboard_style(team, 80.)),
+            ComboText { team },
+            ScoreUi,
+        ));
+        evt_w.send(GenEvent(team, 3));
+    }
 }
 
+fn cleanup_in_game_ui(mut commands: Commands, query: Query<Entity, With<ScoreUi>>) {
+    for ent in &query {
+        commands.entity(ent).despawn_recursive();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn score_system(
     mut counter: ResMut<CounterNumber>,
     mut combo: ResMut<ComboNumber>,
     setting: Res<ScoreSetting>,
+    effects: Res<SpecialEffects>,
+    ex: Res<ExGauges>,
+    clock: Res<SongClock>,
     mut evt_r: EventReader<AttackEvent>,
     mut evt_w: EventWriter<GenEvent>,
+    mut combo_evt_w: EventWriter<ComboEvent>,
 ) {
-    let mut increase_num;
-    let mut gen_num;
-    let mut gen_num2;
     for e in evt_r.read() {
-        if e.0 == 1 {
-            if e.1 {
-                increase_num = combo.score1 + setting.basic_score;
-
-                gen_num = ((counter.score1 + increase_num) / 5) - (counter.score1 / 5);
-                gen_num2 = ((counter.score1 + increase_num) / 10) - (counter.score1 / 10);
-                if gen_num >= 1 {
-                    for _ in 0..gen_num {
-                        evt_w.send(GenEvent(1, 1));
-                        println!("evt_w.send gen={}", gen_num);
-                    }
-                }
-                if gen_num >= 2 {
-                    for _ in 0..gen_num {
-                        evt_w.send(GenEvent(1, 2));
-                        println!("evt_w.send gen={}", gen_num);
-                    }
-                }
-                counter.score1 += combo.score1 + setting.basic_score;
-                combo.score1 += setting.combo_score;
-            } else {
-                combo.score1 = 0;
-                gen_num = 0;
-
-                // TODO: check if miss
-            }
-        } else if e.1 {
-            increase_num = combo.score2 + setting.basic_score;
-            gen_num = ((counter.score2 + increase_num) / 5) - (counter.score2 / 5);
-            gen_num2 = ((counter.score2 + increase_num) / 10) - (counter.score2 / 10);
-            if gen_num >= 1 {
-                for _ in 0..gen_num {
-                    evt_w.send(GenEvent(2, 1));
-                    println!("evt_w.send gen={}", gen_num);
-                }
-            }
-            if gen_num2 >= 2 {
-                for _ in 0..gen_num {
-                    evt_w.send(GenEvent(2, 2));
-                    println!("evt_w.send gen={}", gen_num);
-                }
-            }
-        } else {
-            combo.score2 = 0;
+        let team = e.0;
+        let (Some(score), Some(team_combo)) = (counter.get_mut(team), combo.get_mut(team)) else {
+            continue;
+        };
+
+        let before = *team_combo;
+        // MaZu keeps a combo going through misses
+        let shielded = effects.is_active(team, ImageKey::MaZu, clock.now());
+        let boost = ex.multiplier(team, clock.now());
+        let outcome = setting.score_attack(score, team_combo, e.1, shielded, boost);
+        for _ in 0..outcome.people {
+            evt_w.send(GenEvent(team, 1));
+        }
+        for _ in 0..outcome.cats {
+            evt_w.send(GenEvent(team, 2));
+        }
+        if let Some(combo_evt) = setting.combo_event(team, before, *team_combo) {
+            combo_evt_w.send(combo_evt);
         }
     }
 }
 
-fn counter1_update_system(
-    counter: Res<CounterNumber>,
-    mut query: Query<&mut Text, With<CounterText1>>,
-) {
-    for mut text in &mut query {
-        text.sections[1].value = counter.score1.to_string();
+fn counter_update_system(counter: Res<CounterNumber>, mut query: Query<(&CounterText, &mut Text)>) {
+    for (counter_text, mut text) in &mut query {
+        text.sections[1].value = counter.get(counter_text.team).to_string();
     }
 }
 
-fn counter2_update_system(
-    counter: Res<CounterNumber>,
-    mut query: Query<&mut Text, With<CounterText2>>,
-) {
-    for mut text in &mut query {
-        text.sections[1].value = counter.score2.to_string();
+fn combo_update_system(combo: Res<ComboNumber>, mut query: Query<(&ComboText, &mut Text)>) {
+    for (combo_text, mut text) in &mut query {
+        text.sections[1].value = combo.get(combo_text.team).to_string();
     }
 }
 
-fn combo1_update_system(oombo: Res<ComboNumber>, mut query: Query<&mut Text, With<ComboText1>>) {
-    for mut text in &mut query {
-        text.sections[1].value = oombo.score1.to_string();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::event::Events;
+
+    /// Hits and misses long enough to reach a combo tier and break it.
+    const ATTACKS: [bool; 16] = [
+        true, true, false, true, true, true, true, true, true, true, true, true, true, true, false,
+        true,
+    ];
+
+    fn score_app(teams: usize) -> App {
+        let mut app = App::new();
+        app.add_event::<AttackEvent>()
+            .add_event::<GenEvent>()
+            .add_event::<ComboEvent>()
+            .init_resource::<ScoreSetting>()
+            .init_resource::<SpecialEffects>()
+            .init_resource::<ExGauges>()
+            .init_resource::<SongClock>()
+            .insert_resource(CounterNumber(vec![0; teams]))
+            .insert_resource(ComboNumber(vec![0; teams]))
+            .add_systems(Update, score_system);
+        app
+    }
+
+    /// Play `attacks` for each of `teams` in the same frames, and return what
+    /// every team ended up with.
+    fn play(teams: &[i32], attacks: &[bool]) -> Vec<(usize, usize, Vec<i32>, Vec<ComboEvent>)> {
+        let mut app = score_app(teams.len());
+        let mut crowds = vec![Vec::new(); teams.len()];
+        let mut combo_events = vec![Vec::new(); teams.len()];
+        for attack in attacks {
+            for team in teams {
+                let result = if *attack {
+                    HitResult::Perfect
+                } else {
+                    HitResult::Miss
+                };
+                app.world.send_event(AttackEvent(*team, result));
+            }
+            app.update();
+
+            let gen_events = app.world.resource::<Events<GenEvent>>();
+            for e in gen_events.iter_current_update_events() {
+                crowds[(e.0 - 1) as usize].push(e.1);
+            }
+            let events = app.world.resource::<Events<ComboEvent>>();
+            for e in events.iter_current_update_events() {
+                let (ComboEvent::Tier { team, .. } | ComboEvent::Break { team, .. }) = *e;
+                combo_events[(team - 1) as usize].push(*e);
+            }
+        }
+
+        let counter = app.world.resource::<CounterNumber>();
+        let combo = app.world.resource::<ComboNumber>();
+        (1..=teams.len() as i32)
+            .map(|team| {
+                let index = (team - 1) as usize;
+                (
+                    counter.get(team),
+                    combo.get(team),
+                    crowds[index].clone(),
+                    combo_events[index].clone(),
+                )
+            })
+            .collect()
+    }
+
+    /// The combo events of a team as if they were team 1's.
+    fn as_team_one(events: &[ComboEvent]) -> Vec<ComboEvent> {
+        events
+            .iter()
+            .map(|e| match *e {
+                ComboEvent::Tier { tier, .. } => ComboEvent::Tier { team: 1, tier },
+                ComboEvent::Break { combo, .. } => ComboEvent::Break { team: 1, combo },
+            })
+            .collect()
+    }
+
+    #[test]
+    fn teams_score_identically() {
+        let results = play(&[1, 2], &ATTACKS);
+        let (score, combo, crowd, combo_events) = &results[0];
+        assert!(*score > 0);
+        assert!(!crowd.is_empty());
+        assert!(!combo_events.is_empty());
+        for (other_score, other_combo, other_crowd, other_events) in &results[1..] {
+            assert_eq!(other_score, score);
+            assert_eq!(other_combo, combo);
+            assert_eq!(other_crowd, crowd);
+            assert_eq!(as_team_one(other_events), *combo_events);
+        }
+    }
+
+    #[test]
+    fn every_team_scores_like_team_one() {
+        let results = play(&[1, 2, 3, 4], &ATTACKS);
+        for (score, combo, crowd, combo_events) in &results {
+            assert_eq!(*score, results[0].0);
+            assert_eq!(*combo, results[0].1);
+            assert_eq!(*crowd, results[0].2);
+            assert_eq!(as_team_one(combo_events), results[0].3);
+        }
+    }
+
+    #[test]
+    fn order_within_a_frame_does_not_matter() {
+        assert_eq!(play(&[1, 2], &ATTACKS), play(&[2, 1], &ATTACKS));
+    }
+
+    #[test]
+    fn combo_bonus_rule_adds_the_combo() {
+        let setting = ScoreSetting::default();
+        let (mut score, mut combo) = (0, 0);
+        let outcome = setting.score_attack(&mut score, &mut combo, HitResult::Perfect, false, 1);
+        assert_eq!(outcome.gained, 3);
+        assert_eq!((score, combo), (3, 1));
+        let outcome = setting.score_attack(&mut score, &mut combo, HitResult::Perfect, false, 1);
+        assert_eq!(outcome.gained, 4);
+        assert_eq!((score, combo), (7, 2));
+    }
+
+    #[test]
+    fn misses_break_the_combo_unless_shielded() {
+        let setting = ScoreSetting::default();
+        let (mut score, mut combo) = (10, 5);
+        setting.score_attack(&mut score, &mut combo, HitResult::Miss, true, 1);
+        assert_eq!((score, combo), (10, 5));
+        setting.score_attack(&mut score, &mut combo, HitResult::Miss, false, 1);
+        assert_eq!((score, combo), (10, 0));
+    }
+
+    #[test]
+    fn boost_and_tiers_multiply_points() {
+        let setting = ScoreSetting::default();
+        // 10 combo is the first tier, at 1.2 times
+        let (mut score, mut combo) = (0, 10);
+        let outcome = setting.score_attack(&mut score, &mut combo, HitResult::Perfect, false, 2);
+        assert_eq!(outcome.gained, (13. * 1.2 * 2.) as usize);
+    }
+
+    #[test]
+    fn rules_can_be_swapped() {
+        struct Flat;
+        impl ScoringRule for Flat {
+            fn points(&self, _result: HitResult, _combo: usize) -> usize {
+                100
+            }
+        }
+
+        let setting = ScoreSetting {
+            rule: Box::new(Flat),
+            combo_tiers: Vec::new(),
+            ..default()
+        };
+        let (mut score, mut combo) = (0, 0);
+        for _ in 0..3 {
+            setting.score_attack(&mut score, &mut combo, HitResult::Perfect, false, 1);
+        }
+        assert_eq!((score, combo), (300, 3));
+    }
+
+    #[test]
+    fn modes_set_judgement_scores_and_crowd() {
+        let mode = GameMode {
+            scores: JudgementScores {
+                perfect: 6,
+                good: 3,
+                ok: 1,
+            },
+            combo: ComboCurve {
+                step: 2,
+                bonus: 0.5,
+                cap: Some(4),
+            },
+            crowd: CrowdThresholds {
+                people_every: 2,
+                ..GameMode::default().crowd
+            },
+            ..default()
+        };
+        let setting = ScoreSetting::from_mode(&mode);
+
+        let (mut score, mut combo) = (0, 0);
+        let outcome = setting.score_attack(&mut score, &mut combo, HitResult::Perfect, false, 1);
+        assert_eq!((outcome.gained, outcome.people), (6, 3));
+        assert_eq!(combo, 2);
+        // a combo of 2 adds half a point a combo
+        let outcome = setting.score_attack(&mut score, &mut combo, HitResult::Good, false, 1);
+        assert_eq!(outcome.gained, 4);
+        // the bonus stops growing at a combo of 4
+        let (mut score, mut combo) = (0, 8);
+        let outcome = setting.score_attack(&mut score, &mut combo, HitResult::Ok, false, 1);
+        assert_eq!(outcome.gained, 3);
+    }
+
+    #[test]
+    fn modes_file_parses() {
+        let text = include_str!("../../assets/config/modes.ron");
+        let modes: GameModes = ron::from_str(text).unwrap();
+        assert_eq!(modes.current().name, GameMode::default().name);
+        assert!(modes.modes.len() > 1);
+    }
+
+    #[test]
+    fn modes_pick_their_formula() {
+        let modes: GameModes =
+            ron::from_str(include_str!("../../assets/config/modes.ron")).unwrap();
+        let mode = |name: &str| {
+            let mode = modes.modes.iter().find(|m| m.name == name).unwrap();
+            ScoreSetting::from_mode(mode)
+        };
+        let (classic, steady) = (mode("Classic"), mode("Steady"));
+        assert_eq!(modes.modes[0].scoring, ScoringKind::ComboBonus);
+
+        // the same five Perfects, one per frame
+        let scores = [classic, steady].map(|setting| {
+            let (mut score, mut combo) = (0, 0);
+            for _ in 0..5 {
+                setting.score_attack(&mut score, &mut combo, HitResult::Perfect, false, 1);
+            }
+            (score, combo)
+        });
+        assert_eq!(scores, [(3 + 4 + 5 + 6 + 7, 5), (3 * 5, 5)]);
     }
-}
 
-fn combo2_update_system(combo: Res<ComboNumber>, mut query: Query<&mut Text, With<ComboText2>>) {
-    for mut text in &mut query {
-        text.sections[1].value = combo.score2.to_string();
+    #[test]
+    fn combo_events() {
+        let setting = ScoreSetting::default();
+        assert_eq!(setting.combo_event(2, 8, 9), None);
+        assert_eq!(
+            setting.combo_event(2, 9, 10),
+            Some(ComboEvent::Tier { team: 2, tier: 1 })
+        );
+        assert_eq!(setting.combo_event(2, 9, 0), None);
+        assert_eq!(
+            setting.combo_event(2, 12, 0),
+            Some(ComboEvent::Break { team: 2, combo: 12 })
+        );
     }
 }
@@ -1,9 +1,13 @@
 use bevy::{app::PluginGroupBuilder, prelude::*};
 
 use super::{
-    art::ArtPlugin, character_selection::CharacterSelectionPlugin, game_level::GameLevelUiPlugin,
-    input::GameInputPlugin, score::ScorePlugin, sound_player::SoundSystemPlugin,
-    start_menu::StartMenuPlugin, JumpImagePlugin,
+    art::ArtPlugin, calibration::CalibrationPlugin, character_selection::CharacterSelectionPlugin,
+    chart::ChartPlugin, combat::CombatPlugin, combo_tier::ComboTierPlugin, editor::EditorPlugin,
+    effects::SpecialEffectPlugin, ex::ExPlugin, game_level::GameLevelUiPlugin,
+    hit_error::HitErrorMeterPlugin, input::GameInputPlugin, life::LifePlugin, net::NetPlugin,
+    popup::JudgementPopupPlugin, results::ResultsPlugin, score::ScorePlugin,
+    sound_player::SoundSystemPlugin, spectator::SpectatorPlugin, start_menu::StartMenuPlugin,
+    stats::StatsPlugin, tug::TugOfWarPlugin, JumpImagePlugin,
 };
 
 #[derive(Debug)]
@@ -16,13 +20,28 @@ impl PluginGroup for SeventeenPlugins {
         // default plugins
         builder = builder
             .add(JumpImagePlugin)
+            .add(ChartPlugin)
+            .add(EditorPlugin)
+            .add(CalibrationPlugin)
             .add(GameLevelUiPlugin)
             .add(SoundSystemPlugin)
             .add(CharacterSelectionPlugin)
             .add(StartMenuPlugin)
             .add(GameInputPlugin)
             .add(ArtPlugin)
-            .add(ScorePlugin);
+            .add(ScorePlugin)
+            .add(StatsPlugin)
+            .add(ResultsPlugin)
+            .add(JudgementPopupPlugin)
+            .add(HitErrorMeterPlugin)
+            .add(LifePlugin)
+            .add(TugOfWarPlugin)
+            .add(CombatPlugin)
+            .add(SpecialEffectPlugin)
+            .add(ExPlugin)
+            .add(ComboTierPlugin)
+            .add(NetPlugin)
+            .add(SpectatorPlugin);
 
         #[cfg(all(target_os = "windows", feature = "ringcon"))]
         {
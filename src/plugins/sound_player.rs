@@ -1,35 +1,62 @@
-use super::Team;
-use crate::plugins::input::{PlayerCommand, PlayerCommandEvent};
+use super::{team_row, team_side, Team};
+use crate::config::{GameModes, ImageKey, JudgementWindows};
+use crate::plugins::calibration::Latency;
+use crate::plugins::character_selection::PlayerSettings;
+use crate::plugins::chart::{Chart, SelectedChart};
+use crate::plugins::clock::{Playhead, SongClock, SongTrack};
+use crate::plugins::effects::{DecoyEvent, SpecialEffects};
+use crate::plugins::input::{key_label, PlayerCommand, PlayerCommandEvent};
+use crate::plugins::net::NetSession;
 use crate::plugins::score::AttackEvent;
+use crate::plugins::stats::{Judgement, JudgementEvent};
 use crate::AppState;
-use bevy::audio::{PlaybackMode, Volume};
-use bevy::time::Stopwatch;
-use bevy::{log, prelude::*};
+use bevy::audio::{AddAudioSource, PlaybackMode, Volume};
+use bevy::{log, prelude::*, sprite::Anchor};
 use bevy_tweening::lens::TransformPositionLens;
 use bevy_tweening::{Animator, EaseMethod, Tween};
-use rand::{thread_rng, Rng};
-use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
 
-const BEAT_START: Vec2 = Vec2::new(0., -450.);
-const BEAT_END_P1: Vec2 = Vec2::new(-700., -450.);
-const BEAT_END_P2: Vec2 = Vec2::new(700., -450.);
-const BEAT_RING_OFFSET: f32 = 100.;
+/// Height of the lane row of teams 1 and 2...
+const BEAT_ROW_Y: f32 = -450.;
+/// ...and how much higher each further pair of teams plays.
+const BEAT_ROW_SPACING: f32 = 160.;
+/// Notes travel from the middle of the screen out to a ring this far to the side.
+const BEAT_END_X: f32 = 700.;
+const NOTE_LABEL_FONT_SIZE: f32 = 60.;
+/// How long a note travels from `beat_start` to its ring at scroll speed 1.
+const MOVE_DURATION: Duration = Duration::from_secs(2);
+const HOLD_TAIL_WIDTH: f32 = 60.;
+/// How long the match goes on after the last note of the chart.
+const MATCH_END_DELAY: Duration = Duration::from_secs(2);
+/// How far the song clock may drift from the music before it is set back to
+/// the music's playhead; well above how far ahead the audio output reads.
+const MAX_MUSIC_DRIFT: Duration = Duration::from_millis(100);
 
 #[derive(Debug)]
 pub struct SoundSystemPlugin;
 
 impl Plugin for SoundSystemPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup_sound_system)
-            .add_systems(OnEnter(AppState::InGame), produce_beat_one_shot)
+        app.add_event::<NoteSpawnEvent>()
+            .add_audio_source::<SongTrack>()
+            .init_resource::<SongClock>()
+            .add_systems(Startup, setup_sound_system)
+            .add_systems(OnEnter(AppState::InGame), spawn_rings)
+            .add_systems(OnExit(AppState::InGame), stop_music)
             .add_systems(
                 Update,
                 (
+                    start_music,
+                    sync_song_clock,
                     sound_timer,
                     check_key_down,
                     produce_beat_system,
                     move_beat_system,
+                    hold_tail_system,
                     player_hit_sound_system,
+                    decoy_system,
+                    match_end_system,
                 )
                     .chain()
                     .run_if(in_state(AppState::InGame)),
@@ -39,48 +66,88 @@ impl Plugin for SoundSystemPlugin {
 
 #[derive(Debug, Component)]
 struct BeatControl {
-    stopwatch: Stopwatch,
     time_delta: Duration,
-    last_gen: Duration,
-    allowed_error: Duration,
+    /// One per team, set up once the chart has loaded.
+    streams: Vec<NoteStream>,
+    /// Bar whose downbeat gong is spawned next.
+    next_bar: u32,
+    music_started: bool,
 }
 
+/// A team's own cursor through the chart track it picked.
 #[derive(Debug)]
-enum HitResult {
+struct NoteStream {
+    team: i32,
+    track: usize,
+    lanes: Vec<i32>,
+    /// Index of the next note to spawn.
+    next_note: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HitResult {
     Perfect,
     Good,
     Ok,
     Miss,
 }
 
-fn check_hit_result(error: Duration, delta: Duration) -> Option<HitResult> {
-    if delta > error {
+fn check_hit_result(windows: &JudgementWindows, delta: Duration) -> Option<HitResult> {
+    let delta = delta.as_millis() as u64;
+    if delta > windows.miss {
         return None;
     }
 
-    if delta > error * 3 / 4 {
+    if delta > windows.ok {
         return Some(HitResult::Miss);
     }
 
-    if delta > error / 2 {
+    if delta > windows.good {
         return Some(HitResult::Ok);
     }
 
-    if delta > error / 3 {
+    if delta > windows.perfect {
         return Some(HitResult::Good);
     }
 
     Some(HitResult::Perfect)
 }
 
+/// A note put on `team`'s track, ahead of when it shows up.
+#[derive(Debug, Clone, Copy, Event, Serialize, Deserialize)]
+pub struct NoteSpawnEvent {
+    pub team: i32,
+    pub key: i32,
+    pub hit_point: Duration,
+    pub end_point: Option<Duration>,
+    pub decoy: bool,
+}
+
 #[derive(Debug, Component)]
 struct Beat {
     hit_point: Duration,
+    /// When a hold note has to be released; only the press and the release
+    /// are judged, holding on past the release window is a miss.
+    end_point: Option<Duration>,
     key: i32,
 }
 
-#[derive(Component)]
-pub struct Sound(pub Handle<AudioSource>);
+/// A hold note whose head was hit and is still held down.
+#[derive(Debug, Component)]
+struct Holding;
+
+/// A fake note sent by `NetArmy`; hitting it counts as a wrong key, letting it
+/// pass costs nothing.
+#[derive(Debug, Component)]
+struct Decoy;
+
+/// Body of a hold note, stretched from the note back along its path.
+#[derive(Debug, Component)]
+struct HoldTail;
+
+/// The song track of the current chart.
+#[derive(Debug, Component)]
+pub(crate) struct Music;
 
 #[derive(Resource)]
 pub struct WSound(pub Handle<AudioSource>);
@@ -96,43 +163,233 @@ fn setup_sound_system(mut commands: Commands, asset_server: Res<AssetServer>) {
     commands.insert_resource(WSound(asset_server.load("sounds/W.ogg")));
     commands.insert_resource(DSound(asset_server.load("sounds/D.ogg")));
 
-    let ring_img = asset_server.load("images/ui/game/white.png");
-    commands.spawn(SpriteBundle {
-        texture: ring_img.clone(),
-        transform: Transform {
-            translation: BEAT_END_P1.extend(10.), // + Vec3::new(BEAT_RING_OFFSET, 0., 0.),
-            ..Default::default()
-        },
-        ..Default::default()
-    });
-    commands.spawn(SpriteBundle {
-        texture: ring_img,
-        transform: Transform {
-            translation: BEAT_END_P2.extend(10.), // - Vec3::new(BEAT_RING_OFFSET, 0., 0.),
-            ..Default::default()
-        },
-        ..Default::default()
-    });
-
     commands.spawn(BeatControl {
-        stopwatch: Stopwatch::new(),
         time_delta: Duration::from_secs(5),
-        last_gen: Duration::default(),
-        allowed_error: Duration::from_secs_f32(0.25),
+        streams: Vec::new(),
+        next_bar: 0,
+        music_started: false,
     });
 }
 
-fn sound_timer(
+/// A team's hit ring, spawned for the teams of each match.
+#[derive(Debug, Component)]
+struct Ring;
+
+fn spawn_rings(
     mut commands: Commands,
-    time: Res<Time>,
+    settings: Res<PlayerSettings>,
     asset_server: Res<AssetServer>,
+) {
+    let ring_img = asset_server.load("images/ui/game/white.png");
+    for team in settings.teams() {
+        commands.spawn((
+            SpriteBundle {
+                texture: ring_img.clone(),
+                transform: Transform {
+                    translation: hit_ring(team).extend(10.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            Ring,
+        ));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn start_music(
+    mut commands: Commands,
     mut beat_ctl_query: Query<&mut BeatControl>,
-    beat_query: Query<(Entity, &Beat), Without<Team>>,
+    selected_chart: Res<SelectedChart>,
+    charts: Res<Assets<Chart>>,
+    audio_sources: Res<Assets<AudioSource>>,
+    mut tracks: ResMut<Assets<SongTrack>>,
+    settings: Res<PlayerSettings>,
+    net: Option<Res<NetSession>>,
 ) {
     let mut beat_ctl = beat_ctl_query.get_single_mut().unwrap();
+    if beat_ctl.music_started {
+        return;
+    }
+    // a network match starts at the time agreed on with the peer
+    if net.is_some_and(|net| !net.started()) {
+        return;
+    }
+    let Some(chart) = charts.get(&selected_chart.0) else {
+        return;
+    };
+    let Some(audio) = audio_sources.get(&chart.song.audio) else {
+        return;
+    };
+
+    beat_ctl.streams = settings
+        .teams()
+        .map(|team| {
+            let track = settings.get(team).track.min(chart.tracks.len() - 1);
+            NoteStream {
+                team,
+                track,
+                lanes: chart.track(track).lanes.clone(),
+                next_note: 0,
+            }
+        })
+        .collect();
+
+    let (track, playhead) = SongTrack::new(audio.clone());
+    commands.spawn((
+        AudioSourceBundle {
+            source: tracks.add(track),
+            settings: PlaybackSettings::ONCE,
+        },
+        Music,
+        playhead,
+    ));
+    beat_ctl.music_started = true;
+}
+
+fn stop_music(
+    mut commands: Commands,
+    mut beat_ctl_query: Query<&mut BeatControl>,
+    music_query: Query<Entity, With<Music>>,
+    beat_query: Query<Entity, With<Beat>>,
+    ring_query: Query<Entity, With<Ring>>,
+    mut clock: ResMut<SongClock>,
+) {
+    for ent in music_query.iter().chain(&ring_query) {
+        commands.entity(ent).despawn();
+    }
+    // notes still on their way
+    for ent in &beat_query {
+        commands.entity(ent).despawn_recursive();
+    }
+
+    let mut beat_ctl = beat_ctl_query.get_single_mut().unwrap();
+    clock.reset();
+    beat_ctl.streams.clear();
+    beat_ctl.next_bar = 0;
+    beat_ctl.music_started = false;
+}
+
+/// Keep the song clock in step with the music sink: it starts when the track
+/// actually starts playing, pauses with it, and is set back to the track's
+/// playhead when the two drift apart, e.g. after the audio hitched or started
+/// late.
+pub(crate) fn sync_song_clock(
+    mut clock: ResMut<SongClock>,
+    music_query: Query<(&AudioSink, Option<&Playhead>), With<Music>>,
+    net: Option<Res<NetSession>>,
+) {
+    let Ok((sink, playhead)) = music_query.get_single() else {
+        return;
+    };
+
+    let now = Instant::now();
+    if sink.is_paused() {
+        clock.pause(now);
+        return;
+    }
+    clock.start(now);
+
+    // a network match keeps to the start time agreed with the peer
+    if net.is_some() {
+        return;
+    }
+    let Some(position) = playhead.and_then(Playhead::position) else {
+        return;
+    };
+    let drift = clock.position_at(now).abs_diff(position);
+    if drift > MAX_MUSIC_DRIFT {
+        log::debug!(
+            drift = drift.as_secs_f32(),
+            "song clock set back to the music"
+        );
+        clock.reset();
+        clock.start(now - position);
+    }
+}
+
+/// Scatter `NetArmy` decoys over the half beats of the lanes under attack.
+fn decoy_system(
+    mut commands: Commands,
+    mut decoy_evt: EventReader<DecoyEvent>,
+    beat_ctl_query: Query<&BeatControl>,
+    selected_chart: Res<SelectedChart>,
+    charts: Res<Assets<Chart>>,
+    clock: Res<SongClock>,
+    mut note_evt_w: EventWriter<NoteSpawnEvent>,
+) {
+    let beat_ctl = beat_ctl_query.get_single().unwrap();
+    let Some(chart) = charts.get(&selected_chart.0) else {
+        return;
+    };
+
+    let song = &chart.song;
+    let beat_of = |time: Duration| (time.as_secs_f32() - song.offset) / song.seconds_per_beat();
+    for e in decoy_evt.read() {
+        let Some(stream) = beat_ctl.streams.iter().find(|s| s.team == e.team) else {
+            continue;
+        };
+        // from the first half beat still far enough away to be seen coming
+        let mut beat = ((beat_of(clock.now() + MOVE_DURATION) * 2.).ceil() / 2.).max(0.);
+        let mut lane = 0;
+        while song.beat_time(beat) < e.until {
+            let (hit_point, key) = (
+                song.beat_time(beat),
+                stream.lanes[lane % stream.lanes.len()],
+            );
+            commands.spawn((
+                Beat {
+                    hit_point,
+                    end_point: None,
+                    key,
+                },
+                Team(e.team),
+                Decoy,
+            ));
+            note_evt_w.send(NoteSpawnEvent {
+                team: e.team,
+                key,
+                hit_point,
+                end_point: None,
+                decoy: true,
+            });
+            beat += 0.5;
+            lane += 1;
+        }
+    }
+}
 
-    beat_ctl.stopwatch.tick(time.delta());
-    let elapsed = beat_ctl.stopwatch.elapsed();
+/// End the match once the chart is over or the mode's time limit is up.
+fn match_end_system(
+    beat_ctl_query: Query<&BeatControl>,
+    selected_chart: Res<SelectedChart>,
+    charts: Res<Assets<Chart>>,
+    clock: Res<SongClock>,
+    modes: Res<GameModes>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let beat_ctl = beat_ctl_query.get_single().unwrap();
+    let Some(chart) = charts.get(&selected_chart.0) else {
+        return;
+    };
+    if !beat_ctl.music_started {
+        return;
+    }
+
+    let chart_end = chart.song.beat_time(chart.last_beat()) + MATCH_END_DELAY;
+    if clock.now() >= chart_end.min(modes.current().match_length()) {
+        log::info!("match over");
+        next_state.set(AppState::Results);
+    }
+}
+
+fn sound_timer(
+    mut commands: Commands,
+    clock: Res<SongClock>,
+    asset_server: Res<AssetServer>,
+    beat_query: Query<(Entity, &Beat), Without<Team>>,
+) {
+    let elapsed = clock.now();
     let sound = asset_server.load("sounds/gong.ogg");
     for (ent, beat) in &beat_query {
         // FIXME: hard-coded
@@ -152,69 +409,167 @@ fn sound_timer(
     }
 }
 
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
 fn check_key_down(
     mut player_command_evt: EventReader<PlayerCommandEvent>,
-    beat_query: Query<(Entity, &Beat, &Team), With<MoveBeat>>,
+    beat_query: Query<(Entity, &Beat, &Team, Has<Decoy>), (With<MoveBeat>, Without<Holding>)>,
+    holding_query: Query<(Entity, &Beat, &Team), With<Holding>>,
     mut attack_evt_w: EventWriter<AttackEvent>,
+    mut judgement_evt_w: EventWriter<JudgementEvent>,
     beat_ctl_query: Query<&BeatControl>,
+    settings: Res<PlayerSettings>,
+    latency: Res<Latency>,
+    clock: Res<SongClock>,
+    net: Option<Res<NetSession>>,
     mut commands: Commands,
 ) {
     let beat_ctl = beat_ctl_query.get_single().unwrap();
+    let windows = |team: i32| settings.get(team).difficulty.windows();
+    // holds started this frame, not yet visible through `holding_query`
+    let mut new_holds: Vec<(Entity, i32, i32, Duration)> = Vec::new();
+    // notes already judged this frame, despawned only once commands are applied
+    let mut judged: Vec<Entity> = Vec::new();
+    let mut judge = |team: i32, key: i32, judgement: Judgement, offset: Option<f32>| {
+        judgement_evt_w.send(JudgementEvent {
+            team,
+            key,
+            judgement,
+            offset,
+        });
+    };
 
     for e in player_command_evt.read() {
-        let e_team = Team(e.team);
+        let time = latency.input_time(e.time);
         let key = match e.cmd {
             PlayerCommand::Hit1 => 1,
             PlayerCommand::Hit2 => 2,
             PlayerCommand::Hit3 => 3,
-            PlayerCommand::Exit => {
+            PlayerCommand::Ex | PlayerCommand::Exit => {
                 continue;
             }
         };
+        // keys outside the team's lane set are not part of its track
+        let in_lanes = |stream: &NoteStream| stream.team != e.team || stream.lanes.contains(&key);
+        if !beat_ctl.streams.iter().all(in_lanes) {
+            continue;
+        }
 
-        for (b_ent, beat, b_team) in &beat_query {
-            if *b_team == e_team {
-                let allowed_error = beat_ctl.allowed_error;
-                let delta =
-                    if let Some(diff) = beat.hit_point.checked_sub(beat_ctl.stopwatch.elapsed()) {
-                        diff
-                    } else {
-                        beat_ctl.stopwatch.elapsed() - beat.hit_point
-                    };
-                let Some(hit_result) = check_hit_result(allowed_error, delta) else {
-                    continue;
-                };
-
-                match hit_result {
-                    HitResult::Miss => {
-                        log::debug!(diff = delta.as_secs_f32(), "miss");
-                        attack_evt_w.send(AttackEvent(b_team.0, false));
-                    }
-                    _ => {
-                        if key != beat.key {
-                            log::trace!("wrong key");
-                            attack_evt_w.send(AttackEvent(b_team.0, false));
-                        } else {
-                            log::info!(team = b_team.0, "player attack");
-                            attack_evt_w.send(AttackEvent(b_team.0, true));
-                        }
-                    }
-                }
-                commands.entity(b_ent).despawn_recursive();
+        if !e.pressed {
+            let holding = holding_query
+                .iter()
+                .map(|(b_ent, beat, b_team)| (b_ent, b_team.0, beat.key, beat.end_point.unwrap()))
+                .chain(new_holds.iter().copied())
+                .find(|(_, team, b_key, _)| *team == e.team && *b_key == key);
+            let Some((b_ent, team, _, end_point)) = holding else {
+                continue;
+            };
 
-                break;
+            let release_result = check_hit_result(&windows(team), end_point.abs_diff(time))
+                .unwrap_or(HitResult::Miss);
+            judge(team, key, Judgement::Hit(release_result), None);
+            if release_result != HitResult::Miss {
+                log::info!(team, "player hold");
+            } else {
+                log::debug!(
+                    diff = end_point.as_secs_f32() - time.as_secs_f32(),
+                    "released early"
+                );
             }
+            attack_evt_w.send(AttackEvent(team, release_result));
+            new_holds.retain(|(ent, ..)| *ent != b_ent);
+            commands.entity(b_ent).despawn_recursive();
+            continue;
+        }
+
+        // judge against the input's stamp, not however late in the frame this runs;
+        // the nearest note in the pressed lane is hit, or failing that the nearest
+        // in another, and decoys only catch presses of their own lane
+        let nearest = beat_query
+            .iter()
+            .filter(|&(b_ent, beat, b_team, decoy)| {
+                b_team.0 == e.team && !judged.contains(&b_ent) && (!decoy || beat.key == key)
+            })
+            .filter_map(|(b_ent, beat, b_team, decoy)| {
+                let delta = beat.hit_point.abs_diff(time);
+                let hit_result = check_hit_result(&windows(e.team), delta)?;
+                Some((b_ent, beat, b_team, decoy, delta, hit_result))
+            })
+            .min_by_key(|(_, beat, _, _, delta, _)| (beat.key != key, *delta));
+        let Some((b_ent, beat, b_team, decoy, delta, hit_result)) = nearest else {
+            continue;
+        };
+        let offset = (time.as_secs_f32() - beat.hit_point.as_secs_f32()) * 1000.;
+        judged.push(b_ent);
+
+        let mut hold = None;
+        match hit_result {
+            _ if decoy => {
+                log::debug!(team = b_team.0, "hit a decoy");
+                attack_evt_w.send(AttackEvent(b_team.0, HitResult::Miss));
+                judge(b_team.0, beat.key, Judgement::WrongKey, Some(offset));
+            }
+            HitResult::Miss => {
+                log::debug!(diff = delta.as_secs_f32(), "miss");
+                attack_evt_w.send(AttackEvent(b_team.0, HitResult::Miss));
+                judge(b_team.0, beat.key, Judgement::Hit(hit_result), Some(offset));
+            }
+            _ => {
+                if key != beat.key {
+                    log::trace!("wrong key");
+                    attack_evt_w.send(AttackEvent(b_team.0, HitResult::Miss));
+                    judge(b_team.0, beat.key, Judgement::WrongKey, Some(offset));
+                } else {
+                    log::info!(team = b_team.0, "player attack");
+                    attack_evt_w.send(AttackEvent(b_team.0, hit_result));
+                    judge(b_team.0, beat.key, Judgement::Hit(hit_result), Some(offset));
+                    // inputs that can't be held hit the whole note at once
+                    hold = beat.end_point.filter(|_| !e.tap);
+                }
+            }
+        }
+
+        if let Some(end_point) = hold {
+            // judged again on release
+            commands.entity(b_ent).insert(Holding);
+            new_holds.push((b_ent, b_team.0, beat.key, end_point));
+        } else {
+            commands.entity(b_ent).despawn_recursive();
         }
     }
 
-    let elapsed = beat_ctl.stopwatch.elapsed();
-    for (b_ent, beat, _) in &beat_query {
+    let elapsed = latency.input_time(clock.now());
+    // the peer's notes wait for its inputs to get here
+    let sweep_time = |team: i32| {
+        net.as_ref()
+            .map_or(elapsed, |net| net.sweep_time(team, elapsed))
+    };
+    for (b_ent, beat, b_team, decoy) in &beat_query {
+        let elapsed = sweep_time(b_team.0);
         if elapsed > beat.hit_point
             && matches!(
-                check_hit_result(beat_ctl.allowed_error, elapsed - beat.hit_point),
+                check_hit_result(&windows(b_team.0), elapsed - beat.hit_point),
                 Some(HitResult::Miss) | None
             )
+            && !judged.contains(&b_ent)
+        {
+            // went by without being hit
+            if !decoy {
+                attack_evt_w.send(AttackEvent(b_team.0, HitResult::Miss));
+                judge(b_team.0, beat.key, Judgement::Hit(HitResult::Miss), None);
+            }
+            commands.entity(b_ent).despawn_recursive();
+        }
+    }
+
+    // still held after the last moment it could be released
+    for (b_ent, beat, b_team) in &holding_query {
+        if beat
+            .end_point
+            .is_some_and(|end_point| sweep_time(b_team.0) > end_point + windows(b_team.0).miss())
         {
+            log::debug!(team = b_team.0, "released late");
+            attack_evt_w.send(AttackEvent(b_team.0, HitResult::Miss));
+            judge(b_team.0, beat.key, Judgement::Hit(HitResult::Miss), None);
             commands.entity(b_ent).despawn_recursive();
         }
     }
@@ -235,6 +590,10 @@ fn player_hit_sound_system(
     };
 
     for e in player_command_evt.read() {
+        if !e.pressed {
+            continue;
+        }
+
         match e.cmd {
             PlayerCommand::Hit1 => {
                 commands.spawn(AudioBundle {
@@ -254,7 +613,7 @@ fn player_hit_sound_system(
                     settings: hit_sound_settings,
                 });
             }
-            PlayerCommand::Exit => {}
+            PlayerCommand::Ex | PlayerCommand::Exit => {}
         }
     }
 }
@@ -264,72 +623,149 @@ pub struct MoveBeat {
     pub from: Vec2,
     pub to: Vec2,
     pub duration: Duration,
+    /// Time to travel the whole path at the team's scroll speed.
+    pub approach: Duration,
 }
 
-fn produce_beat_system(mut beat_ctl_query: Query<&mut BeatControl>, mut commands: Commands) {
+fn produce_beat_system(
+    mut beat_ctl_query: Query<&mut BeatControl>,
+    selected_chart: Res<SelectedChart>,
+    charts: Res<Assets<Chart>>,
+    clock: Res<SongClock>,
+    mut note_evt_w: EventWriter<NoteSpawnEvent>,
+    mut commands: Commands,
+) {
     let mut beat_ctl = beat_ctl_query.get_single_mut().unwrap();
+    let Some(chart) = charts.get(&selected_chart.0) else {
+        return;
+    };
 
-    let gen_delta = Duration::from_secs(1);
+    let elapsed = clock.now();
+    let spawn_until = elapsed + beat_ctl.time_delta;
+    for stream in &mut beat_ctl.streams {
+        let track = chart.track(stream.track);
+        while let Some(note) = track.notes.get(stream.next_note) {
+            let hit_point = chart.note_time(note);
+            if hit_point > spawn_until {
+                break;
+            }
+            stream.next_note += 1;
+
+            // the chart was loaded late, the note can no longer be hit
+            if hit_point < elapsed {
+                continue;
+            }
+
+            let end_point = chart.note_end_time(note);
+            commands.spawn((
+                Beat {
+                    hit_point,
+                    end_point,
+                    key: note.key,
+                },
+                Team(stream.team),
+            ));
+            note_evt_w.send(NoteSpawnEvent {
+                team: stream.team,
+                key: note.key,
+                hit_point,
+                end_point,
+                decoy: false,
+            });
+        }
+    }
+
+    // gong on every downbeat until the chart runs out
+    let beats_per_bar = chart.song.beats_per_bar();
     loop {
-        let gen = beat_ctl.last_gen + gen_delta;
-        if gen > beat_ctl.stopwatch.elapsed() + beat_ctl.time_delta {
+        let bar_beat = (beat_ctl.next_bar * beats_per_bar) as f32;
+        let hit_point = chart.song.beat_time(bar_beat);
+        if bar_beat > chart.last_beat() || hit_point > spawn_until {
             break;
         }
+        beat_ctl.next_bar += 1;
 
-        let mut rng = thread_rng();
-        let key = rng.gen_range(1..=3);
-        commands.spawn((
-            Beat {
-                hit_point: gen,
-                key,
-            },
-            Team(1),
-        ));
-        commands.spawn((
-            Beat {
-                hit_point: gen,
-                key,
-            },
-            Team(2),
-        ));
-        commands.spawn(Beat {
-            hit_point: gen,
-            key: -1,
-        });
-        beat_ctl.last_gen = gen;
+        if hit_point >= elapsed {
+            commands.spawn(Beat {
+                hit_point,
+                end_point: None,
+                key: -1,
+            });
+        }
     }
 }
 
-fn produce_beat_one_shot(mut query: Query<&mut BeatControl>, mut commands: Commands) {
-    // let mut beat_ctl = query.get_single_mut().unwrap();
-    // let hit_point = beat_ctl.stopwatch.elapsed() + Duration::from_secs(1);
-    // commands.spawn((Beat { hit_point, key: 1 }, Team(1)));
-    // commands.spawn((Beat { hit_point, key: 1 }, Team(2)));
+/// How long notes take from `beat_start` to their ring at `scroll_speed`.
+pub(crate) fn approach_time(scroll_speed: f32) -> Duration {
+    MOVE_DURATION.div_f32(scroll_speed)
+}
+
+/// Where `team`'s notes have to be hit.
+pub(crate) fn hit_ring(team: i32) -> Vec2 {
+    Vec2::new(
+        team_side(team) * BEAT_END_X,
+        BEAT_ROW_Y + team_row(team) as f32 * BEAT_ROW_SPACING,
+    )
+}
+
+/// Where `team`'s notes set off from.
+pub(crate) fn beat_start(team: i32) -> Vec2 {
+    Vec2::new(0., hit_ring(team).y)
+}
+
+/// Sprite of a note in `key`'s lane, labelled with the team's key for it.
+/// Teams past the second have no art of their own and get a plain note, with
+/// the key written over it by `move_beat_system`.
+pub(crate) fn beat_image(team: i32, key: i32) -> &'static str {
+    match (team, key) {
+        (1, 1) => "images/ui/game/A.png",
+        (1, 2) => "images/ui/game/W.png",
+        (1, 3) => "images/ui/game/D.png",
+        (2, 1) => "images/ui/game/G.png",
+        (2, 2) => "images/ui/game/Y.png",
+        (2, 3) => "images/ui/game/J.png",
+        _ => hold_image(team),
+    }
 }
 
 fn move_beat_system(
     mut commands: Commands,
-    query: Query<(Entity, &Beat, &Team), Without<MoveBeat>>,
-    beat_ctl_query: Query<&BeatControl>,
+    query: Query<(Entity, &Beat, &Team, Has<Decoy>), Without<MoveBeat>>,
+    clock: Res<SongClock>,
+    latency: Res<Latency>,
+    settings: Res<PlayerSettings>,
+    effects: Res<SpecialEffects>,
     asset_server: Res<AssetServer>,
 ) {
-    let move_duration = Duration::from_secs(2);
-    let beat_ctl = beat_ctl_query.get_single().unwrap();
+    let elapsed = clock.now();
 
-    for (ent, beat, team) in &query {
-        let remain_time = beat.hit_point - beat_ctl.stopwatch.elapsed();
-        if remain_time > move_duration {
+    for (ent, beat, team, decoy) in &query {
+        // only how early notes show up changes, not when they are due
+        let approach = approach_time(settings.get(team.0).scroll_speed);
+        let remain_time = latency.display_time(beat.hit_point).saturating_sub(elapsed);
+        if remain_time > approach {
             continue;
         }
+        // the note may have been due too soon to start at `beat_start`
+        let progress = 1. - remain_time.as_secs_f32() / approach.as_secs_f32();
 
-        let (from, to) = match team.0 {
-            1 => (BEAT_START, BEAT_END_P1),
-            2 => (BEAT_START, BEAT_END_P2),
-            _ => panic!(),
+        let to = hit_ring(team.0);
+        let from = if effects.is_active(team.0, ImageKey::Monkey, elapsed) {
+            // from the far side of the ring instead
+            to * 2. - beat_start(team.0)
+        } else {
+            beat_start(team.0)
+        };
+        let alpha = if effects.is_active(team.0, ImageKey::WhyHaRuHiKaGe, elapsed) {
+            0.15
+        } else if decoy {
+            0.6
+        } else {
+            1.
         };
         let z = 30.;
-        let from = Vec3::new(from.x, from.y, z);
-        let to = Vec3::new(to.x, to.y, z);
+        let from = from.lerp(to, progress).extend(z);
+        let to = to.extend(z);
         let tween = Tween::new(
             EaseMethod::Linear,
             remain_time,
@@ -340,18 +776,14 @@ fn move_beat_system(
         );
 
         // TODO: Res
-        let img = match (team.0, beat.key) {
-            (1, 1) => asset_server.load("images/ui/game/A.png"),
-            (1, 2) => asset_server.load("images/ui/game/W.png"),
-            (1, 3) => asset_server.load("images/ui/game/D.png"),
-            (2, 1) => asset_server.load("images/ui/game/G.png"),
-            (2, 2) => asset_server.load("images/ui/game/Y.png"),
-            (2, 3) => asset_server.load("images/ui/game/J.png"),
-            _ => unreachable!(),
-        };
+        let img = asset_server.load(beat_image(team.0, beat.key));
         commands.entity(ent).insert((
             SpriteBundle {
                 texture: img,
+                sprite: Sprite {
+                    color: Color::rgba(1., 1., 1., alpha),
+                    ..default()
+                },
                 transform: Transform {
                     translation: from,
                     ..Default::default()
@@ -363,7 +795,275 @@ fn move_beat_system(
                 from: from.truncate(),
                 to: to.truncate(),
                 duration: remain_time,
+                approach,
             },
         ));
+
+        if beat.end_point.is_some() {
+            let back = from.truncate() - to.truncate();
+            commands.entity(ent).with_children(|parent| {
+                parent.spawn((
+                    SpriteBundle {
+                        texture: asset_server.load(hold_image(team.0)),
+                        sprite: Sprite {
+                            anchor: Anchor::CenterLeft,
+                            custom_size: Some(Vec2::new(0., HOLD_TAIL_WIDTH)),
+                            ..default()
+                        },
+                        transform: Transform {
+                            translation: Vec3::new(0., 0., -1.),
+                            rotation: Quat::from_rotation_z(back.y.atan2(back.x)),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    HoldTail,
+                ));
+            });
+        }
+
+        // plain notes of the teams without art of their own
+        if team.0 > 2 {
+            commands.entity(ent).with_children(|parent| {
+                parent.spawn(Text2dBundle {
+                    text: Text::from_section(
+                        key_label(team.0, beat.key),
+                        TextStyle {
+                            font_size: NOTE_LABEL_FONT_SIZE,
+                            color: Color::BLACK.with_a(alpha),
+                            ..default()
+                        },
+                    ),
+                    transform: Transform::from_xyz(0., 0., 1.),
+                    ..default()
+                });
+            });
+        }
+    }
+}
+
+pub(crate) fn hold_image(team: i32) -> &'static str {
+    match team % 2 {
+        1 => "images/ui/game/blue.png",
+        _ => "images/ui/game/yellow.png",
+    }
+}
+
+/// Stretch each hold tail from its note to where the release point is on the path.
+fn hold_tail_system(
+    clock: Res<SongClock>,
+    latency: Res<Latency>,
+    beat_query: Query<(&Beat, &MoveBeat, &Children)>,
+    mut tail_query: Query<&mut Sprite, With<HoldTail>>,
+) {
+    let elapsed = clock.now();
+    for (beat, move_beat, children) in &beat_query {
+        let Some(end_point) = beat.end_point else {
+            continue;
+        };
+
+        let travel = move_beat.duration.as_secs_f32().max(f32::EPSILON);
+        let speed = move_beat.from.distance(move_beat.to) / travel;
+        let head = latency.display_time(beat.hit_point).saturating_sub(elapsed);
+        let tail = latency
+            .display_time(end_point)
+            .saturating_sub(elapsed)
+            .min(move_beat.approach);
+        let length = tail.saturating_sub(head).as_secs_f32() * speed;
+        for child in children {
+            if let Ok(mut sprite) = tail_query.get_mut(*child) {
+                sprite.custom_size = Some(Vec2::new(length, HOLD_TAIL_WIDTH));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::effects::SpecialEffects;
+    use crate::plugins::ex::ExGauges;
+    use crate::plugins::score::{
+        score_system, ComboEvent, ComboNumber, CounterNumber, GenEvent, ScoreSetting,
+    };
+    use bevy::ecs::event::Events;
+
+    /// Two teams with `combo` each, whose notes `check_key_down` judges `now`
+    /// into the song and `score_system` scores.
+    fn match_app(now: Duration, combo: usize) -> App {
+        let mut clock = SongClock::default();
+        clock.start(Instant::now() - now);
+
+        let mut app = App::new();
+        app.add_event::<PlayerCommandEvent>()
+            .add_event::<AttackEvent>()
+            .add_event::<JudgementEvent>()
+            .add_event::<GenEvent>()
+            .add_event::<ComboEvent>()
+            .init_resource::<PlayerSettings>()
+            .init_resource::<Latency>()
+            .init_resource::<ScoreSetting>()
+            .init_resource::<SpecialEffects>()
+            .init_resource::<ExGauges>()
+            .insert_resource(clock)
+            .insert_resource(CounterNumber(vec![0; 2]))
+            .insert_resource(ComboNumber(vec![combo; 2]))
+            .add_systems(Update, (check_key_down, score_system).chain());
+        app.world.spawn(BeatControl {
+            time_delta: Duration::ZERO,
+            streams: Vec::new(),
+            next_bar: 0,
+            music_started: true,
+        });
+        app
+    }
+
+    fn press(app: &mut App, team: i32, cmd: PlayerCommand, time: Duration) {
+        app.world.send_event(PlayerCommandEvent {
+            team,
+            cmd,
+            pressed: true,
+            tap: false,
+            time,
+        });
+    }
+
+    fn spawn_note(app: &mut App, team: i32, key: i32, hit_point: Duration) -> Entity {
+        app.world
+            .spawn((
+                Beat {
+                    hit_point,
+                    end_point: None,
+                    key,
+                },
+                Team(team),
+                MoveBeat {
+                    from: Vec2::ZERO,
+                    to: Vec2::ZERO,
+                    duration: MOVE_DURATION,
+                    approach: MOVE_DURATION,
+                },
+            ))
+            .id()
+    }
+
+    #[test]
+    fn notes_that_go_by_break_the_combo() {
+        let mut app = match_app(Duration::from_secs(3), 5);
+        spawn_note(&mut app, 1, 1, Duration::from_secs(1));
+        app.update();
+
+        let combo = app.world.resource::<ComboNumber>();
+        assert_eq!((combo.get(1), combo.get(2)), (0, 5));
+        assert!(app.world.query::<&Beat>().iter(&app.world).next().is_none());
+    }
+
+    #[test]
+    fn tier_drops_when_a_note_goes_by() {
+        // 12 combo is past the first tier
+        let mut app = match_app(Duration::from_secs(3), 12);
+        spawn_note(&mut app, 2, 3, Duration::from_secs(1));
+        app.update();
+
+        let setting = app.world.resource::<ScoreSetting>();
+        let combo = app.world.resource::<ComboNumber>();
+        assert_eq!(setting.tier(combo.get(2)), 0);
+        let combo_events = app.world.resource::<Events<ComboEvent>>();
+        let combo_events: Vec<_> = combo_events.iter_current_update_events().copied().collect();
+        assert_eq!(combo_events, [ComboEvent::Break { team: 2, combo: 12 }]);
+    }
+
+    #[test]
+    fn expired_notes_are_scored_as_misses() {
+        let secs = Duration::from_secs_f32;
+        let mut app = match_app(secs(3.), 0);
+        spawn_note(&mut app, 1, 1, secs(1.));
+        spawn_note(&mut app, 1, 2, secs(2.));
+        spawn_note(&mut app, 1, 3, secs(2.5));
+        press(&mut app, 1, PlayerCommand::Hit1, secs(1.));
+        press(&mut app, 1, PlayerCommand::Hit2, secs(2.));
+        app.update();
+        spawn_note(&mut app, 1, 1, secs(4.));
+        press(&mut app, 1, PlayerCommand::Hit1, secs(4.));
+        app.update();
+
+        // the same as scoring the judgements one by one
+        let setting = ScoreSetting::default();
+        let (mut score, mut combo) = (0, 0);
+        for result in [
+            HitResult::Perfect,
+            HitResult::Perfect,
+            HitResult::Miss,
+            HitResult::Perfect,
+        ] {
+            setting.score_attack(&mut score, &mut combo, result, false, 1);
+        }
+        let counter = app.world.resource::<CounterNumber>();
+        let combo_number = app.world.resource::<ComboNumber>();
+        assert_eq!((counter.get(1), combo_number.get(1)), (score, combo));
+        assert_eq!(combo, 1);
+    }
+
+    #[test]
+    fn presses_hit_the_nearest_note_of_their_lane() {
+        let secs = Duration::from_secs_f32;
+        let mut app = match_app(Duration::ZERO, 0);
+        // a decoy in another lane comes before the note pressed for, and another
+        // note of the lane right after it
+        let decoy = spawn_note(&mut app, 1, 2, secs(1.95));
+        app.world.entity_mut(decoy).insert(Decoy);
+        spawn_note(&mut app, 1, 1, secs(2.1));
+        let aimed = spawn_note(&mut app, 1, 1, secs(2.));
+        press(&mut app, 1, PlayerCommand::Hit1, secs(2.04));
+        app.update();
+
+        let judgements = app.world.resource::<Events<JudgementEvent>>();
+        let judgements: Vec<_> = judgements
+            .iter_current_update_events()
+            .map(|e| (e.key, e.judgement))
+            .collect();
+        assert_eq!(judgements.len(), 1);
+        assert_eq!(judgements[0].0, 1);
+        assert!(matches!(
+            judgements[0].1,
+            Judgement::Hit(HitResult::Perfect | HitResult::Good)
+        ));
+        assert!(app.world.get_entity(aimed).is_none());
+        assert!(app.world.get_entity(decoy).is_some());
+    }
+
+    #[test]
+    fn holds_are_judged_on_press_and_release() {
+        let secs = Duration::from_secs_f32;
+        let mut app = match_app(Duration::ZERO, 0);
+        let held = spawn_note(&mut app, 1, 1, secs(1.));
+        let tapped = spawn_note(&mut app, 2, 1, secs(1.));
+        for note in [held, tapped] {
+            app.world.get_mut::<Beat>(note).unwrap().end_point = Some(secs(2.));
+        }
+        press(&mut app, 1, PlayerCommand::Hit1, secs(1.));
+        app.world.send_event(PlayerCommandEvent {
+            team: 2,
+            cmd: PlayerCommand::Hit1,
+            pressed: true,
+            tap: true,
+            time: secs(1.),
+        });
+        app.update();
+
+        let combo = app.world.resource::<ComboNumber>();
+        assert_eq!((combo.get(1), combo.get(2)), (1, 1));
+        assert!(app.world.get::<Holding>(held).is_some());
+        assert!(app.world.get_entity(tapped).is_none());
+
+        // never let go
+        let mut clock = app.world.resource_mut::<SongClock>();
+        clock.reset();
+        clock.start(Instant::now() - secs(3.));
+        app.update();
+
+        let combo = app.world.resource::<ComboNumber>();
+        assert_eq!((combo.get(1), combo.get(2)), (0, 1));
+        assert!(app.world.get_entity(held).is_none());
     }
 }
@@ -0,0 +1,775 @@
+use crate::plugins::character_selection::PlayerSettings;
+use crate::plugins::clock::SongClock;
+use crate::plugins::ex::{ExGauge, ExGauges};
+use crate::plugins::input::key_label;
+use crate::plugins::life::{LifeGauge, LifeGauges};
+use crate::plugins::net::parse_address;
+use crate::plugins::score::{scoreboard_style, ComboNumber, CounterNumber};
+use crate::plugins::sound_player::{
+    approach_time, beat_image, beat_start, hit_ring, HitResult, NoteSpawnEvent,
+};
+use crate::plugins::stats::{Judgement, JudgementEvent};
+use crate::AppState;
+use bevy::{log, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::io::ErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+const DEFAULT_PORT: u16 = 7778;
+/// How often spectators tell the broadcaster they are still watching...
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+/// ...and how long it keeps sending to one that has gone quiet.
+const SPECTATOR_TIMEOUT: Duration = Duration::from_secs(5);
+/// Most events sent in one datagram; the rest follow in more.
+const FRAME_EVENTS: usize = 32;
+/// How far the spectator's clock may drift from the broadcast before it is
+/// set again.
+const MAX_CLOCK_DRIFT: Duration = Duration::from_millis(100);
+/// Notes nobody judged are cleared this long after they were due.
+const NOTE_LINGER: Duration = Duration::from_secs(1);
+const NOTE_LABEL_FONT_SIZE: f32 = 60.;
+const SCOREBOARD_FONT_SIZE: f32 = 40.;
+const SCOREBOARD_COLOR: Color = Color::rgb(1.0, 0.5, 0.5);
+const LOBBY_FONT_SIZE: f32 = 40.;
+
+/// Show a match on a second machine, e.g. a projector, without controlling it:
+///
+/// - `--broadcast [port]` streams every match played here to spectators,
+/// - `--spectate <address>[:port]` watches a broadcasting machine.
+///
+/// Every frame, the broadcaster sends its song position, each team's score,
+/// life and EX gauge, and the note spawns and judgements each spectator has
+/// not acknowledged yet.
+#[derive(Debug)]
+pub struct SpectatorPlugin;
+
+impl Plugin for SpectatorPlugin {
+    fn build(&self, app: &mut App) {
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--broadcast" => {
+                    let port = args.next().and_then(|port| port.parse().ok());
+                    add_broadcaster(app, port.unwrap_or(DEFAULT_PORT));
+                }
+                "--spectate" => {
+                    let host = args
+                        .next()
+                        .and_then(|host| parse_address(&host, DEFAULT_PORT));
+                    let Some(host) = host else {
+                        log::error!("--spectate needs the broadcaster's address");
+                        continue;
+                    };
+                    add_spectator(app, host);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// One datagram from the broadcaster.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Frame {
+    /// Song position of the match, `None` between matches.
+    now: Option<Duration>,
+    teams: Vec<TeamState>,
+    /// Events the spectator has, all before it included, as far as the
+    /// broadcaster knows or still keeps.
+    since: u64,
+    /// Last event of the broadcaster.
+    latest: u64,
+    /// Events after `since`, oldest first, numbered from 1; split over several
+    /// frames when there are many.
+    events: Vec<(u64, SpectatorEvent)>,
+}
+
+/// What a spectator sends back, now and then and whenever it has new events.
+#[derive(Debug, Serialize, Deserialize)]
+struct Watch {
+    /// Last event the spectator has, all before it included.
+    ack: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TeamState {
+    score: usize,
+    combo: usize,
+    /// Empty gauges for a team that has none, so the teams after it keep theirs.
+    life: LifeGauge,
+    ex: ExGauge,
+    scroll_speed: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum SpectatorEvent {
+    Note(NoteSpawnEvent),
+    Judged {
+        team: i32,
+        key: i32,
+        judgement: Judgement,
+        /// Whether there was an input, see `JudgementEvent::offset`.
+        pressed: bool,
+    },
+}
+
+#[derive(Debug, Resource)]
+struct Broadcaster {
+    socket: UdpSocket,
+    spectators: Vec<Watcher>,
+    next_event: u64,
+    /// Events some spectator has not acknowledged, oldest first.
+    unacked: VecDeque<(u64, SpectatorEvent)>,
+}
+
+#[derive(Debug)]
+struct Watcher {
+    address: SocketAddr,
+    last_heard: Instant,
+    /// Last event it acknowledged.
+    acked: u64,
+}
+
+impl Broadcaster {
+    fn bind(port: u16) -> std::io::Result<Self> {
+        let socket = UdpSocket::bind(SocketAddr::from(([0, 0, 0, 0], port)))?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            spectators: Vec::new(),
+            next_event: 1,
+            unacked: VecDeque::new(),
+        })
+    }
+
+    fn push(&mut self, event: SpectatorEvent) {
+        self.unacked.push_back((self.next_event, event));
+        self.next_event += 1;
+    }
+
+    /// Take in what the spectators said: anyone who says hello is watching.
+    fn receive(&mut self, now: Instant) {
+        let mut buf = [0; 64];
+        loop {
+            let (len, from) = match self.socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    log::trace!("broadcast receive: {e}");
+                    continue;
+                }
+            };
+            let watch = std::str::from_utf8(&buf[..len])
+                .map_err(|e| e.to_string())
+                .and_then(|text| ron::from_str::<Watch>(text).map_err(|e| e.to_string()));
+            let watch = match watch {
+                Ok(watch) => watch,
+                Err(e) => {
+                    log::debug!(?from, "bad spectator datagram: {e}");
+                    continue;
+                }
+            };
+            // acks past our last event are from before this broadcaster started
+            let ack = watch.ack.min(self.next_event - 1);
+            match self.spectators.iter_mut().find(|w| w.address == from) {
+                Some(watcher) => {
+                    watcher.last_heard = now;
+                    watcher.acked = watcher.acked.max(ack);
+                }
+                None => {
+                    log::info!(spectator = ?from, "spectator joined");
+                    self.spectators.push(Watcher {
+                        address: from,
+                        last_heard: now,
+                        acked: ack,
+                    });
+                }
+            }
+        }
+        self.spectators
+            .retain(|w| now - w.last_heard < SPECTATOR_TIMEOUT);
+
+        let acked = self.spectators.iter().map(|w| w.acked).min();
+        let acked = acked.unwrap_or(self.next_event - 1);
+        while self.unacked.front().is_some_and(|(seq, _)| *seq <= acked) {
+            self.unacked.pop_front();
+        }
+    }
+
+    /// Send every spectator the state of the match and the events it has not
+    /// acknowledged.
+    fn send(&self, now: Option<Duration>, teams: &[TeamState]) {
+        let oldest = self
+            .unacked
+            .front()
+            .map_or(self.next_event, |(seq, _)| *seq);
+        for watcher in &self.spectators {
+            let since = watcher.acked.max(oldest - 1);
+            let events: Vec<_> = self
+                .unacked
+                .iter()
+                .filter(|(seq, _)| *seq > since)
+                .cloned()
+                .collect();
+            let mut chunks: Vec<_> = events.chunks(FRAME_EVENTS).collect();
+            if chunks.is_empty() {
+                chunks.push(&[]);
+            }
+            for chunk in chunks {
+                let frame = Frame {
+                    now,
+                    teams: teams.to_vec(),
+                    since,
+                    latest: self.next_event - 1,
+                    events: chunk.to_vec(),
+                };
+                let text = match ron::to_string(&frame) {
+                    Ok(text) => text,
+                    Err(e) => {
+                        log::error!("could not encode a broadcast frame: {e}");
+                        return;
+                    }
+                };
+                if let Err(e) = self.socket.send_to(text.as_bytes(), watcher.address) {
+                    log::trace!(spectator = ?watcher.address, "broadcast send: {e}");
+                }
+            }
+        }
+    }
+}
+
+fn add_broadcaster(app: &mut App, port: u16) {
+    let broadcaster = match Broadcaster::bind(port) {
+        Ok(broadcaster) => broadcaster,
+        Err(e) => {
+            log::error!(port, "could not open the broadcast socket: {e}");
+            return;
+        }
+    };
+    log::info!(port, "broadcasting to spectators");
+
+    app.insert_resource(broadcaster)
+        .add_systems(PostUpdate, broadcast_system);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn broadcast_system(
+    mut broadcaster: ResMut<Broadcaster>,
+    mut note_evt: EventReader<NoteSpawnEvent>,
+    mut judgement_evt: EventReader<JudgementEvent>,
+    state: Res<State<AppState>>,
+    clock: Res<SongClock>,
+    settings: Res<PlayerSettings>,
+    counter: Res<CounterNumber>,
+    combo: Res<ComboNumber>,
+    life: Res<LifeGauges>,
+    ex: Res<ExGauges>,
+) {
+    broadcaster.receive(Instant::now());
+    for e in note_evt.read() {
+        broadcaster.push(SpectatorEvent::Note(*e));
+    }
+    for e in judgement_evt.read() {
+        broadcaster.push(SpectatorEvent::Judged {
+            team: e.team,
+            key: e.key,
+            judgement: e.judgement,
+            pressed: e.offset.is_some(),
+        });
+    }
+    if broadcaster.spectators.is_empty() {
+        return;
+    }
+
+    if *state.get() != AppState::InGame {
+        broadcaster.send(None, &[]);
+        return;
+    }
+    let index = |team: i32| (team - 1) as usize;
+    let teams: Vec<TeamState> = settings
+        .teams()
+        .map(|team| TeamState {
+            score: counter.get(team),
+            combo: combo.get(team),
+            life: life.0.get(index(team)).cloned().unwrap_or_default(),
+            ex: ex.0.get(index(team)).cloned().unwrap_or_default(),
+            scroll_speed: settings.get(team).scroll_speed,
+        })
+        .collect();
+    broadcaster.send(Some(clock.now()), &teams);
+}
+
+#[derive(Debug, Resource)]
+struct Spectator {
+    socket: UdpSocket,
+    host: SocketAddr,
+    last_watch: Option<Instant>,
+    last_heard: Option<Instant>,
+    /// Last event applied, all before it included.
+    last_event: u64,
+    /// Last event acknowledged to the broadcaster.
+    acked: u64,
+    /// Events received out of order, waiting for the ones before them.
+    pending: BTreeMap<u64, SpectatorEvent>,
+    /// Latest frame received, not applied yet; its events are in `pending`.
+    frame: Option<Frame>,
+}
+
+impl Spectator {
+    fn bind(host: SocketAddr) -> std::io::Result<Self> {
+        let socket = UdpSocket::bind(SocketAddr::from(([0, 0, 0, 0], 0)))?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            host,
+            last_watch: None,
+            last_heard: None,
+            last_event: 0,
+            acked: 0,
+            pending: BTreeMap::new(),
+            frame: None,
+        })
+    }
+
+    /// Say hello now and then and whenever there are new events to
+    /// acknowledge, and take in the frames from the broadcaster.
+    fn receive(&mut self, now: Instant) {
+        if self.acked != self.last_event
+            || self
+                .last_watch
+                .is_none_or(|watch| now - watch >= WATCH_INTERVAL)
+        {
+            self.last_watch = Some(now);
+            self.acked = self.last_event;
+            let watch = Watch {
+                ack: self.last_event,
+            };
+            let sent = ron::to_string(&watch)
+                .map_err(|e| e.to_string())
+                .and_then(|text| {
+                    let sent = self.socket.send_to(text.as_bytes(), self.host);
+                    sent.map_err(|e| e.to_string())
+                });
+            if let Err(e) = sent {
+                log::trace!("spectator send: {e}");
+            }
+        }
+
+        let mut buf = vec![0; 65536];
+        loop {
+            let len = match self.socket.recv_from(&mut buf) {
+                Ok((len, from)) if from == self.host => len,
+                Ok(_) => continue,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    log::trace!("spectator receive: {e}");
+                    continue;
+                }
+            };
+            let frame = std::str::from_utf8(&buf[..len])
+                .map_err(|e| e.to_string())
+                .and_then(|text| ron::from_str::<Frame>(text).map_err(|e| e.to_string()));
+            match frame {
+                Ok(frame) => {
+                    self.last_heard = Some(now);
+                    self.take_frame(frame);
+                }
+                Err(e) => log::warn!("bad broadcast frame: {e}"),
+            }
+        }
+    }
+
+    /// Keep the state of `frame`, and its events not applied yet.
+    fn take_frame(&mut self, mut frame: Frame) {
+        // a broadcaster started again numbers its events from 1 again
+        if frame.latest < self.last_event {
+            self.last_event = 0;
+            self.pending.clear();
+        }
+        // the broadcaster no longer has the events up to `since`, e.g. for a
+        // spectator that just joined
+        if frame.since > self.last_event {
+            if self.last_event > 0 {
+                log::warn!(
+                    missed = frame.since - self.last_event,
+                    "broadcast events lost"
+                );
+            }
+            self.last_event = frame.since;
+            self.pending = self.pending.split_off(&(frame.since + 1));
+        }
+        for (seq, event) in frame.events.drain(..) {
+            if seq > self.last_event {
+                self.pending.insert(seq, event);
+            }
+        }
+        self.frame = Some(frame);
+    }
+
+    /// The event after the last one applied, once it has come.
+    fn next_event(&mut self) -> Option<SpectatorEvent> {
+        let event = self.pending.remove(&(self.last_event + 1))?;
+        self.last_event += 1;
+        Some(event)
+    }
+}
+
+/// A note of the broadcast match, moved along its track by the spectator's
+/// clock.
+#[derive(Debug, Component)]
+struct SpectatorNote {
+    team: i32,
+    key: i32,
+    hit_point: Duration,
+    end_point: Option<Duration>,
+}
+
+/// A hold note whose head was hit, waiting on its ring for the release.
+#[derive(Debug, Component)]
+struct Held;
+
+#[derive(Debug, Component)]
+struct SpectatorScoreText {
+    team: i32,
+}
+
+/// Everything spawned for the match being watched.
+#[derive(Debug, Component)]
+struct SpectatorUi;
+
+#[derive(Debug, Component)]
+struct LobbyText;
+
+fn add_spectator(app: &mut App, host: SocketAddr) {
+    let spectator = match Spectator::bind(host) {
+        Ok(spectator) => spectator,
+        Err(e) => {
+            log::error!(?host, "could not open the spectator socket: {e}");
+            return;
+        }
+    };
+    log::info!(?host, "spectating");
+
+    app.insert_resource(spectator)
+        .add_systems(Startup, |mut next_state: ResMut<NextState<AppState>>| {
+            next_state.set(AppState::SpectatorLobby);
+        })
+        .add_systems(OnEnter(AppState::SpectatorLobby), setup_lobby)
+        .add_systems(OnExit(AppState::SpectatorLobby), cleanup::<LobbyText>)
+        .add_systems(OnEnter(AppState::Spectating), setup_spectating)
+        .add_systems(OnExit(AppState::Spectating), cleanup::<SpectatorUi>)
+        .add_systems(
+            PreUpdate,
+            (receive_frame_system, apply_frame_system).chain(),
+        )
+        .add_systems(
+            Update,
+            (
+                lobby_text_update_system.run_if(in_state(AppState::SpectatorLobby)),
+                (note_move_system, scoreboard_update_system).run_if(in_state(AppState::Spectating)),
+            ),
+        );
+}
+
+fn setup_lobby(mut commands: Commands) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: LOBBY_FONT_SIZE,
+                color: Color::rgb(0.9, 0.9, 0.9),
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Percent(45.),
+            left: Val::Percent(30.),
+            ..default()
+        }),
+        LobbyText,
+    ));
+}
+
+fn setup_spectating(mut commands: Commands, settings: Res<PlayerSettings>) {
+    let text_style = TextStyle {
+        font_size: SCOREBOARD_FONT_SIZE,
+        color: SCOREBOARD_COLOR,
+        ..default()
+    };
+    for team in settings.teams() {
+        commands.spawn((
+            TextBundle::from_sections([
+                TextSection::new(format!("Player{team}\nScore: "), text_style.clone()),
+                TextSection::from_style(text_style.clone()),
+                TextSection::new("\nCombo: ", text_style.clone()),
+                TextSection::from_style(text_style.clone()),
+            ])
+            .with_style(scoreboard_style(team, 5.)),
+            SpectatorScoreText { team },
+            SpectatorUi,
+        ));
+    }
+}
+
+fn cleanup<T: Component>(mut commands: Commands, query: Query<Entity, With<T>>) {
+    for ent in &query {
+        commands.entity(ent).despawn_recursive();
+    }
+}
+
+fn receive_frame_system(mut spectator: ResMut<Spectator>) {
+    spectator.receive(Instant::now());
+}
+
+/// Mirror the broadcast match: the state it is in, its clock, the gauges
+/// `GameLevelUiPlugin` draws, and its notes.
+#[allow(clippy::too_many_arguments)]
+fn apply_frame_system(
+    mut commands: Commands,
+    mut spectator: ResMut<Spectator>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut clock: ResMut<SongClock>,
+    mut settings: ResMut<PlayerSettings>,
+    mut counter: ResMut<CounterNumber>,
+    mut combo: ResMut<ComboNumber>,
+    mut life: ResMut<LifeGauges>,
+    mut ex: ResMut<ExGauges>,
+    note_query: Query<(Entity, &SpectatorNote, Has<Held>)>,
+    asset_server: Res<AssetServer>,
+) {
+    let Some(frame) = spectator.frame.take() else {
+        return;
+    };
+    let Some(now) = frame.now else {
+        if *state.get() == AppState::Spectating {
+            next_state.set(AppState::SpectatorLobby);
+        }
+        return;
+    };
+
+    if *state.get() != AppState::Spectating {
+        log::info!(teams = frame.teams.len(), "match started");
+        settings.set_count(frame.teams.len());
+        clock.reset();
+        next_state.set(AppState::Spectating);
+    }
+    for (team, team_state) in (1..).zip(&frame.teams) {
+        settings.get_mut(team).scroll_speed = team_state.scroll_speed;
+    }
+    counter.0 = frame.teams.iter().map(|t| t.score).collect();
+    combo.0 = frame.teams.iter().map(|t| t.combo).collect();
+    life.0 = frame.teams.iter().map(|t| t.life.clone()).collect();
+    ex.0 = frame.teams.iter().map(|t| t.ex.clone()).collect();
+
+    if clock.now().abs_diff(now) > MAX_CLOCK_DRIFT {
+        clock.reset();
+        clock.start(Instant::now() - now);
+    }
+
+    // notes already judged this frame, despawned only once commands are applied
+    let mut judged: Vec<Entity> = Vec::new();
+    while let Some(event) = spectator.next_event() {
+        match event {
+            SpectatorEvent::Note(note) => {
+                spawn_note(&mut commands, &asset_server, note);
+            }
+            SpectatorEvent::Judged {
+                team,
+                key,
+                judgement,
+                pressed,
+            } => {
+                let judged_at = clock.now();
+                // presses go to notes on their way, releases to held ones
+                let nearest = note_query
+                    .iter()
+                    .filter(|(ent, note, _)| {
+                        note.team == team && note.key == key && !judged.contains(ent)
+                    })
+                    .min_by_key(|(_, note, held)| {
+                        (pressed == *held, note.hit_point.abs_diff(judged_at))
+                    });
+                let Some((ent, note, held)) = nearest else {
+                    continue;
+                };
+                let hit = pressed && !matches!(judgement, Judgement::Hit(HitResult::Miss));
+                if hit && note.end_point.is_some() && !held {
+                    commands.entity(ent).insert(Held);
+                } else {
+                    commands.entity(ent).despawn_recursive();
+                }
+                judged.push(ent);
+            }
+        }
+    }
+}
+
+fn spawn_note(commands: &mut Commands, asset_server: &AssetServer, note: NoteSpawnEvent) {
+    let alpha = if note.decoy { 0.6 } else { 1. };
+    let mut ent = commands.spawn((
+        SpriteBundle {
+            texture: asset_server.load(beat_image(note.team, note.key)),
+            sprite: Sprite {
+                color: Color::rgba(1., 1., 1., alpha),
+                ..default()
+            },
+            transform: Transform::from_translation(beat_start(note.team).extend(30.)),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        SpectatorNote {
+            team: note.team,
+            key: note.key,
+            hit_point: note.hit_point,
+            end_point: note.end_point,
+        },
+        SpectatorUi,
+    ));
+    // plain notes of the teams without art of their own
+    if note.team > 2 {
+        ent.with_children(|parent| {
+            parent.spawn(Text2dBundle {
+                text: Text::from_section(
+                    key_label(note.team, note.key),
+                    TextStyle {
+                        font_size: NOTE_LABEL_FONT_SIZE,
+                        color: Color::BLACK.with_a(alpha),
+                        ..default()
+                    },
+                ),
+                transform: Transform::from_xyz(0., 0., 1.),
+                ..default()
+            });
+        });
+    }
+}
+
+/// Move every note along its track by the clock, and clear those left over.
+fn note_move_system(
+    mut commands: Commands,
+    clock: Res<SongClock>,
+    settings: Res<PlayerSettings>,
+    mut query: Query<(
+        Entity,
+        &SpectatorNote,
+        Has<Held>,
+        &mut Transform,
+        &mut Visibility,
+    )>,
+) {
+    let now = clock.now();
+    for (ent, note, held, mut transform, mut visibility) in &mut query {
+        let due = if held {
+            note.end_point.unwrap_or(note.hit_point)
+        } else {
+            note.hit_point
+        };
+        if now > due + NOTE_LINGER {
+            commands.entity(ent).despawn_recursive();
+            continue;
+        }
+
+        let approach = approach_time(settings.get(note.team).scroll_speed);
+        let remain = note.hit_point.saturating_sub(now);
+        if remain > approach {
+            continue;
+        }
+        let progress = 1. - remain.as_secs_f32() / approach.as_secs_f32();
+        let position = beat_start(note.team).lerp(hit_ring(note.team), progress);
+        transform.translation = position.extend(transform.translation.z);
+        *visibility = Visibility::Inherited;
+    }
+}
+
+fn scoreboard_update_system(
+    counter: Res<CounterNumber>,
+    combo: Res<ComboNumber>,
+    mut query: Query<(&SpectatorScoreText, &mut Text)>,
+) {
+    for (score_text, mut text) in &mut query {
+        text.sections[1].value = counter.get(score_text.team).to_string();
+        text.sections[3].value = combo.get(score_text.team).to_string();
+    }
+}
+
+fn lobby_text_update_system(
+    spectator: Res<Spectator>,
+    mut query: Query<&mut Text, With<LobbyText>>,
+) {
+    let heard = spectator
+        .last_heard
+        .is_some_and(|heard| heard.elapsed() < SPECTATOR_TIMEOUT);
+    let status = if heard {
+        format!("Watching {}\nWaiting for the next match...", spectator.host)
+    } else {
+        format!("Looking for a broadcast at {}...", spectator.host)
+    };
+    for mut text in &mut query {
+        text.sections[0].value = status.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(key: i32) -> SpectatorEvent {
+        SpectatorEvent::Note(NoteSpawnEvent {
+            team: 1,
+            key,
+            hit_point: Duration::from_secs(1),
+            end_point: None,
+            decoy: false,
+        })
+    }
+
+    /// Let both sides send and take in what the other sent, a few times over,
+    /// and return the keys of the notes the spectator got in order.
+    fn exchange(broadcaster: &mut Broadcaster, spectator: &mut Spectator) -> Vec<i32> {
+        let mut keys = Vec::new();
+        for _ in 0..10 {
+            spectator.receive(Instant::now());
+            std::thread::sleep(Duration::from_millis(5));
+            broadcaster.receive(Instant::now());
+            broadcaster.send(Some(Duration::ZERO), &[]);
+            std::thread::sleep(Duration::from_millis(5));
+            spectator.receive(Instant::now());
+            while let Some(event) = spectator.next_event() {
+                match event {
+                    SpectatorEvent::Note(note) => keys.push(note.key),
+                    other => panic!("unexpected {other:?}"),
+                }
+            }
+        }
+        keys
+    }
+
+    #[test]
+    fn events_are_sent_until_acknowledged() {
+        // any free port
+        let mut broadcaster = Broadcaster::bind(0).unwrap();
+        let port = broadcaster.socket.local_addr().unwrap().port();
+        let mut spectator = Spectator::bind(SocketAddr::from(([127, 0, 0, 1], port))).unwrap();
+        exchange(&mut broadcaster, &mut spectator);
+        assert_eq!(broadcaster.spectators.len(), 1);
+
+        // more than fit in a frame, and the first frames get lost
+        for key in 0..100 {
+            broadcaster.push(note(key));
+        }
+        spectator.receive(Instant::now());
+        broadcaster.receive(Instant::now());
+        broadcaster.send(Some(Duration::ZERO), &[]);
+        std::thread::sleep(Duration::from_millis(5));
+        while spectator.socket.recv_from(&mut [0; 65536]).is_ok() {}
+
+        let keys = exchange(&mut broadcaster, &mut spectator);
+        assert_eq!(keys, (0..100).collect::<Vec<_>>());
+        assert_eq!(spectator.last_event, 100);
+        assert!(broadcaster.unacked.is_empty());
+    }
+}
@@ -7,7 +7,10 @@ pub struct StartMenuPlugin;
 impl Plugin for StartMenuPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(OnEnter(AppState::Menu), setup_menu)
-            .add_systems(Update, menu.run_if(in_state(AppState::Menu)))
+            .add_systems(
+                Update,
+                (menu, menu_shortcuts).run_if(in_state(AppState::Menu)),
+            )
             .add_systems(OnExit(AppState::Menu), cleanup_menu);
     }
 }
@@ -84,6 +87,7 @@ fn setup_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
         });
 }
 
+#[allow(clippy::type_complexity)]
 fn menu(
     mut next_state: ResMut<NextState<AppState>>,
     mut interaction_query: Query<
@@ -92,16 +96,21 @@ fn menu(
     >,
 ) {
     for (interaction, _color) in &mut interaction_query {
-        match *interaction {
-            Interaction::Pressed => {
-                // next_state.set(AppState::CharacterSelection);
-                next_state.set(AppState::InGame);
-            }
-            _ => {}
+        if *interaction == Interaction::Pressed {
+            next_state.set(AppState::CharacterSelection);
         }
     }
 }
 
+fn menu_shortcuts(keys: Res<Input<KeyCode>>, mut next_state: ResMut<NextState<AppState>>) {
+    if keys.just_pressed(KeyCode::E) {
+        next_state.set(AppState::Editor);
+    }
+    if keys.just_pressed(KeyCode::C) {
+        next_state.set(AppState::Calibration);
+    }
+}
+
 fn cleanup_menu(mut commands: Commands, query: Query<Entity, With<StartMenuTag>>) {
     for ent in &query {
         commands.entity(ent).despawn_recursive();
@@ -0,0 +1,151 @@
+use crate::plugins::character_selection::PlayerSettings;
+use crate::plugins::sound_player::HitResult;
+use crate::AppState;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Tally every judgement of the match per team, for the results screen.
+#[derive(Debug)]
+pub struct StatsPlugin;
+
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<JudgementEvent>()
+            .init_resource::<MatchStats>()
+            .add_systems(OnEnter(AppState::InGame), reset_stats)
+            .add_systems(Update, stats_system.run_if(in_state(AppState::InGame)));
+    }
+}
+
+/// How one note of `team`'s track was played.
+#[derive(Debug, Event)]
+pub struct JudgementEvent {
+    pub team: i32,
+    /// Lane of the note.
+    pub key: i32,
+    pub judgement: Judgement,
+    /// Milliseconds the input was late by, negative when early; `None` when
+    /// there was no input to time, e.g. a note that went by or a hold release.
+    pub offset: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Judgement {
+    Hit(HitResult),
+    /// Hit in time, but with a key for another lane.
+    WrongKey,
+}
+
+/// Per-team statistics of the current (or last) match.
+#[derive(Debug, Default, Resource)]
+pub struct MatchStats(Vec<TeamStats>);
+
+impl MatchStats {
+    pub fn get(&self, team: i32) -> &TeamStats {
+        &self.0[(team - 1) as usize]
+    }
+
+    fn get_mut(&mut self, team: i32) -> Option<&mut TeamStats> {
+        self.0.get_mut((team - 1) as usize)
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct TeamStats {
+    pub perfect: usize,
+    pub good: usize,
+    pub ok: usize,
+    pub miss: usize,
+    pub wrong_key: usize,
+    pub combo: usize,
+    pub max_combo: usize,
+    offset_count: usize,
+    offset_sum: f32,
+    offset_square_sum: f32,
+}
+
+impl TeamStats {
+    fn record(&mut self, judgement: Judgement, offset: Option<f32>) {
+        match judgement {
+            Judgement::Hit(HitResult::Perfect) => self.perfect += 1,
+            Judgement::Hit(HitResult::Good) => self.good += 1,
+            Judgement::Hit(HitResult::Ok) => self.ok += 1,
+            Judgement::Hit(HitResult::Miss) => self.miss += 1,
+            Judgement::WrongKey => self.wrong_key += 1,
+        }
+
+        if matches!(
+            judgement,
+            Judgement::Hit(HitResult::Miss) | Judgement::WrongKey
+        ) {
+            self.combo = 0;
+        } else {
+            self.combo += 1;
+            self.max_combo = self.max_combo.max(self.combo);
+        }
+
+        if let Some(offset) = offset {
+            self.offset_count += 1;
+            self.offset_sum += offset;
+            self.offset_square_sum += offset * offset;
+        }
+    }
+
+    pub fn judged(&self) -> usize {
+        self.perfect + self.good + self.ok + self.miss + self.wrong_key
+    }
+
+    /// Mean timing offset in milliseconds, positive when the player tends to be late.
+    pub fn mean_offset(&self) -> Option<f32> {
+        (self.offset_count > 0).then(|| self.offset_sum / self.offset_count as f32)
+    }
+
+    pub fn offset_std_dev(&self) -> Option<f32> {
+        let mean = self.mean_offset()?;
+        let variance = self.offset_square_sum / self.offset_count as f32 - mean * mean;
+        Some(variance.max(0.).sqrt())
+    }
+
+    /// Percentage of the best possible play: a Perfect counts fully, a Good
+    /// three quarters and an Ok half. 0 when nothing was judged, so a team
+    /// that sat the match out doesn't rank at the top.
+    pub fn accuracy(&self) -> f32 {
+        if self.judged() == 0 {
+            return 0.;
+        }
+        let points = self.perfect as f32 + self.good as f32 * 0.75 + self.ok as f32 * 0.5;
+        points / self.judged() as f32 * 100.
+    }
+}
+
+impl std::fmt::Display for TeamStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Accuracy {:.2}%  Max combo {}",
+            self.accuracy(),
+            self.max_combo
+        )?;
+        writeln!(
+            f,
+            "Perfect {}  Good {}  Ok {}\nMiss {}  Wrong key {}",
+            self.perfect, self.good, self.ok, self.miss, self.wrong_key
+        )?;
+        match (self.mean_offset(), self.offset_std_dev()) {
+            (Some(mean), Some(std_dev)) => write!(f, "Offset {mean:+.1}ms +/- {std_dev:.1}ms"),
+            _ => write!(f, "Offset -"),
+        }
+    }
+}
+
+fn reset_stats(mut stats: ResMut<MatchStats>, settings: Res<PlayerSettings>) {
+    stats.0 = vec![TeamStats::default(); settings.teams().count()];
+}
+
+fn stats_system(mut judgement_evt: EventReader<JudgementEvent>, mut stats: ResMut<MatchStats>) {
+    for e in judgement_evt.read() {
+        if let Some(team_stats) = stats.get_mut(e.team) {
+            team_stats.record(e.judgement, e.offset);
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use crate::config::ImageKey;
+use crate::plugins::art::SedanChair;
+use crate::plugins::character_selection::PlayerSettings;
+use crate::plugins::clock::SongClock;
+use crate::plugins::effects::SpecialEffects;
+use crate::plugins::score::AttackEvent;
+use crate::plugins::sound_player::HitResult;
+use crate::plugins::team_side;
+use crate::AppState;
+use bevy::{log, prelude::*};
+
+/// How far one successful attack pulls the rope, out of 1 to a knockout.
+const TUG_PUSH: f32 = 0.03;
+/// How far the sedan chairs travel when the rope is pulled all the way.
+const CHAIR_TRAVEL: f32 = 400.;
+const METER_Y: f32 = 500.;
+const METER_SIZE: Vec2 = Vec2::new(600., 12.);
+const MARKER_SIZE: Vec2 = Vec2::new(8., 30.);
+
+/// The processions on each half of the field pull on one shared rope; whoever
+/// pulls it all the way wins by knockout for their half.
+#[derive(Debug)]
+pub struct TugOfWarPlugin;
+
+impl Plugin for TugOfWarPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TugOfWar>()
+            .add_systems(OnEnter(AppState::InGame), setup_tug_of_war)
+            .add_systems(
+                Update,
+                (tug_system, advance_chair_system, tug_meter_update_system)
+                    .chain()
+                    .run_if(in_state(AppState::InGame)),
+            )
+            .add_systems(OnExit(AppState::InGame), cleanup_tug_of_war);
+    }
+}
+
+#[derive(Debug, Default, Resource)]
+pub struct TugOfWar {
+    /// From -1 (the right half won) to 1 (the left half won), 0 when even.
+    pub position: f32,
+    /// Team that pulled the rope all the way, if any.
+    pub knockout: Option<i32>,
+    /// Teams on the left and on the right half.
+    side_teams: [usize; 2],
+}
+
+impl TugOfWar {
+    /// Pull the rope `amount` towards `team`'s opponents. A half with more
+    /// teams on it pulls that much less per team.
+    pub fn pull(&mut self, team: i32, amount: f32) {
+        if self.knockout.is_some() {
+            return;
+        }
+        let side = team_side(team);
+        let teams = self.side_teams[if side < 0. { 0 } else { 1 }].max(1);
+        self.position = (self.position - side * amount / teams as f32).clamp(-1., 1.);
+        if self.position.abs() >= 1. {
+            self.knockout = Some(team);
+        }
+    }
+
+    /// Whether `team` is on the half that won by knockout.
+    pub fn knocked_out_by(&self, team: i32) -> bool {
+        self.knockout
+            .is_some_and(|winner| team_side(winner) == team_side(team))
+    }
+}
+
+#[derive(Debug, Component)]
+struct TugUi;
+
+#[derive(Debug, Component)]
+struct TugMarker;
+
+fn setup_tug_of_war(
+    mut commands: Commands,
+    mut tug: ResMut<TugOfWar>,
+    settings: Res<PlayerSettings>,
+) {
+    let left = settings
+        .teams()
+        .filter(|team| team_side(*team) < 0.)
+        .count();
+    *tug = TugOfWar {
+        side_teams: [left, settings.teams().count() - left],
+        ..default()
+    };
+
+    // each half in the colour of the team whose side it is
+    for (x, color) in [
+        (-METER_SIZE.x / 4., Color::rgb(0.3, 0.5, 1.)),
+        (METER_SIZE.x / 4., Color::rgb(1., 0.85, 0.2)),
+    ] {
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color,
+                    custom_size: Some(Vec2::new(METER_SIZE.x / 2., METER_SIZE.y)),
+                    ..default()
+                },
+                transform: Transform::from_xyz(x, METER_Y, 20.),
+                ..default()
+            },
+            TugUi,
+        ));
+    }
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::WHITE,
+                custom_size: Some(MARKER_SIZE),
+                ..default()
+            },
+            transform: Transform::from_xyz(0., METER_Y, 21.),
+            ..default()
+        },
+        TugMarker,
+        TugUi,
+    ));
+}
+
+fn tug_system(
+    mut attack_evt: EventReader<AttackEvent>,
+    mut tug: ResMut<TugOfWar>,
+    effects: Res<SpecialEffects>,
+    clock: Res<SongClock>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    for e in attack_evt.read() {
+        if e.1 == HitResult::Miss {
+            continue;
+        }
+        let boost = if effects.is_active(e.0, ImageKey::GenShinStart, clock.now()) {
+            2.
+        } else {
+            1.
+        };
+        tug.pull(e.0, TUG_PUSH * boost);
+    }
+
+    if let Some(team) = tug.knockout {
+        log::info!(team, "knockout");
+        next_state.set(AppState::Results);
+    }
+}
+
+/// Move every chair with the rope, the leading half's towards the other side.
+fn advance_chair_system(tug: Res<TugOfWar>, mut query: Query<(&SedanChair, &mut Transform)>) {
+    for (chair, mut transform) in &mut query {
+        transform.translation.x = chair.home_x + tug.position * CHAIR_TRAVEL;
+    }
+}
+
+fn tug_meter_update_system(tug: Res<TugOfWar>, mut query: Query<&mut Transform, With<TugMarker>>) {
+    for mut transform in &mut query {
+        transform.translation.x = tug.position * METER_SIZE.x / 2.;
+    }
+}
+
+fn cleanup_tug_of_war(mut commands: Commands, query: Query<Entity, With<TugUi>>) {
+    for ent in &query {
+        commands.entity(ent).despawn();
+    }
+}